@@ -0,0 +1,311 @@
+use std::{
+    io::{self, Stdout, Write},
+    mem, ptr,
+    sync::mpsc::Sender,
+    thread,
+};
+
+use termion::{
+    input::{MouseTerminal, TermRead},
+    raw::{IntoRawMode, RawTerminal},
+    screen::{AlternateScreen, IntoAlternateScreen},
+};
+
+use crate::{common::Size, event_loop::EditorEvent};
+
+/// Where the editor draws and gets its input from.
+pub trait Backend {
+    fn size(&self) -> Size;
+    /// Starts sending input and resizes to `events`, `InputClosed` once
+    /// there is no more input.
+    fn listen(&mut self, events: Sender<EditorEvent>);
+    fn write(&mut self, out: &str);
+    fn flush(&mut self);
+}
+
+/// The real terminal, in raw mode on the alternate screen with mouse
+/// reporting, all undone on drop.
+pub struct TermionBackend {
+    screen: MouseTerminal<AlternateScreen<RawTerminal<Stdout>>>,
+    // SIGWINCH, blocked so a thread can wait for it
+    resize_signal: libc::sigset_t,
+}
+
+impl TermionBackend {
+    /// Has to be called before any other thread is started, which would
+    /// get the SIGWINCH meant for the thread waiting for it.
+    pub fn new() -> Self {
+        let screen = io::stdout()
+            .into_raw_mode()
+            .unwrap()
+            .into_alternate_screen()
+            .unwrap();
+        // SAFETY: the set is initialized by sigemptyset before use, and only
+        // the signal mask of the calling thread is changed, threads started
+        // later inherit it
+        let resize_signal = unsafe {
+            let mut set: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, libc::SIGWINCH);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+            set
+        };
+        Self {
+            screen: MouseTerminal::from(screen),
+            resize_signal,
+        }
+    }
+}
+
+impl Backend for TermionBackend {
+    fn size(&self) -> Size {
+        let (width, height) = termion::terminal_size().unwrap();
+        Size {
+            height: height as usize,
+            width: width as usize,
+        }
+    }
+
+    fn listen(&mut self, events: Sender<EditorEvent>) {
+        let input = events.clone();
+        thread::spawn(move || {
+            for event in io::stdin().events() {
+                let Ok(event) = event else {
+                    break;
+                };
+                if input.send(EditorEvent::Input(event)).is_err() {
+                    return;
+                }
+            }
+            let _ = input.send(EditorEvent::InputClosed);
+        });
+
+        // a signal arrives as an event like key presses
+        let set = self.resize_signal;
+        thread::spawn(move || loop {
+            let mut signal = 0;
+            // SAFETY: set is a valid signal set holding a blocked signal
+            if unsafe { libc::sigwait(&set, &mut signal) } != 0 {
+                return;
+            }
+            if events.send(EditorEvent::Resize).is_err() {
+                return;
+            }
+        });
+    }
+
+    fn write(&mut self, out: &str) {
+        write!(self.screen, "{}", out).unwrap();
+    }
+
+    fn flush(&mut self) {
+        self.screen.flush().unwrap();
+    }
+}
+
+#[cfg(test)]
+pub use memory::MemoryBackend;
+
+#[cfg(test)]
+mod memory {
+    use std::{cell::RefCell, rc::Rc, sync::mpsc::Sender};
+
+    use termion::event::Event;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use super::Backend;
+    use crate::{
+        common::Size,
+        event_loop::{EditorEvent, Timer},
+        text,
+    };
+
+    struct State {
+        size: Size,
+        // set by listen
+        events: Option<Sender<EditorEvent>>,
+        // graphemes on screen, empty for the second cell of a wide one
+        grid: Vec<Vec<String>>,
+        // 0 based row and column
+        cursor: (usize, usize),
+        cursor_visible: bool,
+        // rows moved by scrolling, inclusive
+        scroll_region: (usize, usize),
+    }
+
+    /// A screen kept in memory for tests, fed with scripted events. It
+    /// understands the escapes the editor writes and keeps the resulting
+    /// grid of cells. Clones share the same screen.
+    #[derive(Clone)]
+    pub struct MemoryBackend {
+        state: Rc<RefCell<State>>,
+    }
+
+    impl MemoryBackend {
+        pub fn new(width: usize, height: usize) -> Self {
+            Self {
+                state: Rc::new(RefCell::new(State {
+                    size: Size { height, width },
+                    events: None,
+                    grid: vec![vec![" ".to_string(); width]; height],
+                    cursor: (0, 0),
+                    cursor_visible: true,
+                    scroll_region: (0, height.saturating_sub(1)),
+                })),
+            }
+        }
+
+        /// Sends `events` and then `InputClosed`, so the editor stops
+        /// running once it has handled them.
+        pub fn push_events(&self, events: impl IntoIterator<Item = Event>) {
+            let events = events
+                .into_iter()
+                .map(EditorEvent::Input)
+                .chain([EditorEvent::InputClosed]);
+            events.for_each(|event| self.send(event));
+        }
+
+        /// Fires `timer` as if it were due and then sends `InputClosed`,
+        /// so tests don't wait for the clock.
+        pub fn fire(&self, timer: Timer) {
+            self.send(EditorEvent::Timer(timer));
+            self.send(EditorEvent::InputClosed);
+        }
+
+        fn send(&self, event: EditorEvent) {
+            if let Some(events) = &self.state.borrow().events {
+                let _ = events.send(event);
+            }
+        }
+
+        /// Changes the size like a resized window, the grid is cleared.
+        pub fn resize(&self, width: usize, height: usize) {
+            let mut state = self.state.borrow_mut();
+            state.size = Size { height, width };
+            state.grid = vec![vec![" ".to_string(); width]; height];
+            state.scroll_region = (0, height.saturating_sub(1));
+            drop(state);
+            self.send(EditorEvent::Resize);
+        }
+
+        /// The rows of the screen as text, trailing spaces cut, and the
+        /// cursor as a `▏` when it is shown.
+        pub fn snapshot(&self) -> String {
+            let state = self.state.borrow();
+            let mut rows = vec![];
+            for (row, cells) in state.grid.iter().enumerate() {
+                let mut line = String::new();
+                for (col, cell) in cells.iter().enumerate() {
+                    if state.cursor_visible && state.cursor == (row, col) {
+                        line.push('▏');
+                    }
+                    line.push_str(cell);
+                }
+                rows.push(line.trim_end().to_string());
+            }
+            rows.join("\n")
+        }
+    }
+
+    impl State {
+        fn print(&mut self, g: &str) {
+            let width = text::grapheme_width(g).max(1);
+            let (row, col) = self.cursor;
+            if row < self.size.height && col + width <= self.size.width {
+                self.grid[row][col] = g.to_string();
+                for cell in &mut self.grid[row][col + 1..col + width] {
+                    cell.clear();
+                }
+            }
+            self.cursor.1 += width;
+        }
+
+        fn scroll(&mut self, up: bool) {
+            let (top, bottom) = self.scroll_region;
+            let blank = vec![" ".to_string(); self.size.width];
+            match up {
+                true => {
+                    self.grid.remove(top);
+                    self.grid.insert(bottom, blank);
+                }
+                false => {
+                    self.grid.remove(bottom);
+                    self.grid.insert(top, blank);
+                }
+            }
+        }
+
+        // a CSI sequence without the leading `ESC [`
+        fn control(&mut self, params: &str, end: char) {
+            let numbers: Vec<usize> = params
+                .trim_start_matches('?')
+                .split(';')
+                .map(|n| n.parse().unwrap_or(0))
+                .collect();
+            let number = |i: usize, default: usize| match numbers.get(i) {
+                Some(0) | None => default,
+                Some(n) => *n,
+            };
+            match (params, end) {
+                ("?25", 'l') => self.cursor_visible = false,
+                ("?25", 'h') => self.cursor_visible = true,
+                (_, 'H') => self.cursor = (number(0, 1) - 1, number(1, 1) - 1),
+                ("2", 'J') => {
+                    self.grid = vec![vec![" ".to_string(); self.size.width]; self.size.height]
+                }
+                (_, 'r') => {
+                    self.scroll_region = (number(0, 1) - 1, number(1, self.size.height) - 1);
+                    self.cursor = (0, 0);
+                }
+                (_, 'S') => (0..number(0, 1)).for_each(|_| self.scroll(true)),
+                (_, 'T') => (0..number(0, 1)).for_each(|_| self.scroll(false)),
+                // styles and modes don't change the text
+                _ => {}
+            }
+        }
+    }
+
+    impl Backend for MemoryBackend {
+        fn size(&self) -> Size {
+            self.state.borrow().size
+        }
+
+        fn listen(&mut self, events: Sender<EditorEvent>) {
+            self.state.borrow_mut().events = Some(events);
+        }
+
+        fn write(&mut self, out: &str) {
+            let mut state = self.state.borrow_mut();
+            let mut rest = out;
+            while !rest.is_empty() {
+                if let Some(csi) = rest.strip_prefix("\x1b[") {
+                    let Some(end) = csi.find(|ch: char| ('@'..='~').contains(&ch)) else {
+                        break;
+                    };
+                    state.control(&csi[..end], csi[end..].chars().next().unwrap_or(' '));
+                    rest = &csi[end + 1..];
+                } else if let Some(osc) = rest.strip_prefix("\x1b]") {
+                    // the clipboard escape, up to its BEL
+                    rest = osc.split_once('\x07').map_or("", |(_, rest)| rest);
+                } else {
+                    let g = rest.graphemes(true).next().unwrap_or(rest);
+                    match g {
+                        "\r" => state.cursor.1 = 0,
+                        "\n" => state.cursor.0 += 1,
+                        "\r\n" => state.cursor = (state.cursor.0 + 1, 0),
+                        // to the next tab stop, every 8 cells, leaving the
+                        // cells passed as they were
+                        "\t" => {
+                            let stop = (state.cursor.1 / 8 + 1) * 8;
+                            state.cursor.1 = stop.min(state.size.width.saturating_sub(1));
+                        }
+                        _ => state.print(g),
+                    }
+                    rest = &rest[g.len()..];
+                }
+            }
+        }
+
+        fn flush(&mut self) {}
+    }
+}
@@ -0,0 +1,340 @@
+use std::{cmp::Ordering, ops::Range};
+
+use crate::common::Position;
+
+// Lines are kept in an implicit treap: every node is one line and its
+// position in the document is given by the sizes of the subtrees to its left.
+// Lookup, insert and remove of a line are O(log n), so editing a huge file
+// costs about the same as editing a small one.
+type Link = Option<Box<Node>>;
+
+struct Node {
+    line: String,
+    priority: u32,
+    size: usize,
+    left: Link,
+    right: Link,
+}
+
+impl Node {
+    fn new(line: String, priority: u32) -> Box<Self> {
+        Box::new(Self {
+            line,
+            priority,
+            size: 1,
+            left: None,
+            right: None,
+        })
+    }
+
+    fn update(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+fn size(link: &Link) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+// split link into (first `at` lines, remaining lines)
+fn split(link: Link, at: usize) -> (Link, Link) {
+    match link {
+        None => (None, None),
+        Some(mut node) => {
+            let left_size = size(&node.left);
+            if at <= left_size {
+                let (left, right) = split(node.left.take(), at);
+                node.left = right;
+                node.update();
+                (left, Some(node))
+            } else {
+                let (left, right) = split(node.right.take(), at - left_size - 1);
+                node.right = left;
+                node.update();
+                (Some(node), right)
+            }
+        }
+    }
+}
+
+fn merge(left: Link, right: Link) -> Link {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority >= right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.update();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// Line oriented text storage used by `Doc`.
+///
+/// A buffer always holds at least one (possibly empty) line. Columns passed to
+/// the editing functions are byte indices into the line.
+pub struct Buffer {
+    root: Link,
+    seed: u32,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::from_lines(vec![String::new()])
+    }
+
+    pub fn from_lines(lines: Vec<String>) -> Self {
+        let mut buffer = Self {
+            root: None,
+            seed: 0x9e37_79b9,
+        };
+        let mut lines: Vec<Option<String>> = lines.into_iter().map(Some).collect();
+        if lines.is_empty() {
+            lines.push(Some(String::new()));
+        }
+        buffer.root = Self::build(&mut lines, u32::MAX);
+        buffer
+    }
+
+    // Builds a perfectly balanced tree, priorities shrink with depth so the
+    // heap property of the treap holds.
+    fn build(lines: &mut [Option<String>], priority: u32) -> Link {
+        if lines.is_empty() {
+            return None;
+        }
+        let mid = lines.len() / 2;
+        let mut node = Node::new(lines[mid].take().unwrap_or_default(), priority);
+        let (left, right) = lines.split_at_mut(mid);
+        node.left = Self::build(left, priority / 2);
+        node.right = Self::build(&mut right[1..], priority / 2);
+        node.update();
+        Some(node)
+    }
+
+    fn next_priority(&mut self) -> u32 {
+        // xorshift32
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        x
+    }
+
+    pub fn len_lines(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn line(&self, idx: usize) -> &str {
+        let mut link = &self.root;
+        let mut idx = idx;
+        while let Some(node) = link {
+            let left_size = size(&node.left);
+            match idx.cmp(&left_size) {
+                Ordering::Less => link = &node.left,
+                Ordering::Equal => return &node.line,
+                Ordering::Greater => {
+                    idx -= left_size + 1;
+                    link = &node.right;
+                }
+            }
+        }
+        panic!("line index out of bounds");
+    }
+
+    pub fn line_mut(&mut self, idx: usize) -> &mut String {
+        let mut link = &mut self.root;
+        let mut idx = idx;
+        while let Some(node) = link {
+            let left_size = size(&node.left);
+            match idx.cmp(&left_size) {
+                Ordering::Less => link = &mut node.left,
+                Ordering::Equal => return &mut node.line,
+                Ordering::Greater => {
+                    idx -= left_size + 1;
+                    link = &mut node.right;
+                }
+            }
+        }
+        panic!("line index out of bounds");
+    }
+
+    pub fn insert_line(&mut self, idx: usize, line: String) {
+        assert!(idx <= self.len_lines(), "line index out of bounds");
+        let node = Node::new(line, self.next_priority());
+        let (left, right) = split(self.root.take(), idx);
+        self.root = merge(merge(left, Some(node)), right);
+    }
+
+    pub fn remove_line(&mut self, idx: usize) -> String {
+        assert!(idx < self.len_lines(), "line index out of bounds");
+        let (left, right) = split(self.root.take(), idx);
+        let (removed, right) = split(right, 1);
+        self.root = merge(left, right);
+        if self.root.is_none() {
+            self.root = Some(Node::new(String::new(), self.next_priority()));
+        }
+        removed.map(|node| node.line).unwrap_or_default()
+    }
+
+    pub fn remove_range(&mut self, row: usize, range: Range<usize>) -> String {
+        self.line_mut(row).drain(range).collect()
+    }
+
+    /// Inserts `text`, which may span several lines, at byte position `pos`
+    /// and returns the position right after it.
+    pub fn insert_text(&mut self, pos: Position, text: &str) -> Position {
+        let mut parts = text.split('\n');
+        let tail = self.line_mut(pos.row).split_off(pos.col);
+        self.line_mut(pos.row)
+            .push_str(parts.next().unwrap_or_default());
+
+        let mut row = pos.row;
+        for part in parts {
+            row += 1;
+            self.insert_line(row, part.to_string());
+        }
+        let col = self.line(row).len();
+        self.line_mut(row).push_str(&tail);
+        Position { row, col }
+    }
+
+    /// Removes the text between byte positions `start` and `end` and returns it.
+    pub fn delete_text(&mut self, start: Position, end: Position) -> String {
+        if start.row == end.row {
+            return self.remove_range(start.row, start.col..end.col);
+        }
+        let mut removed = self.line_mut(start.row).split_off(start.col);
+        for _ in start.row + 1..end.row {
+            removed.push('\n');
+            removed.push_str(&self.remove_line(start.row + 1));
+        }
+        let last = self.remove_line(start.row + 1);
+        removed.push('\n');
+        removed.push_str(&last[..end.col]);
+        self.line_mut(start.row).push_str(&last[end.col..]);
+        removed
+    }
+
+    pub fn lines(&self) -> Lines<'_> {
+        self.lines_from(0)
+    }
+
+    /// Iterates over lines starting at line `start`.
+    pub fn lines_from(&self, start: usize) -> Lines<'_> {
+        let mut stack = Vec::new();
+        let mut link = &self.root;
+        let mut idx = start;
+        while let Some(node) = link {
+            let left_size = size(&node.left);
+            if idx <= left_size {
+                stack.push(node.as_ref());
+                link = &node.left;
+            } else {
+                idx -= left_size + 1;
+                link = &node.right;
+            }
+        }
+        Lines { stack }
+    }
+}
+
+pub struct Lines<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let mut link = &node.right;
+        while let Some(child) = link {
+            self.stack.push(child);
+            link = &child.left;
+        }
+        Some(&node.line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(lines: &[&str]) -> Buffer {
+        Buffer::from_lines(lines.iter().map(|line| line.to_string()).collect())
+    }
+
+    fn contents(buffer: &Buffer) -> Vec<&str> {
+        buffer.lines().collect()
+    }
+
+    fn pos(row: usize, col: usize) -> Position {
+        Position { row, col }
+    }
+
+    #[test]
+    fn insert_multi_line_text() {
+        let mut buffer = buffer(&["one", "two"]);
+        let end = buffer.insert_text(pos(0, 2), "X\nYY\nZ");
+        assert_eq!(end, pos(2, 1));
+        assert_eq!(contents(&buffer), ["onX", "YY", "Ze", "two"]);
+    }
+
+    #[test]
+    fn insert_at_the_end() {
+        let mut buffer = buffer(&["one", "two"]);
+        let end = buffer.insert_text(pos(1, 3), "\nthree\n");
+        assert_eq!(end, pos(3, 0));
+        assert_eq!(contents(&buffer), ["one", "two", "three", ""]);
+        buffer.insert_line(4, "five".to_string());
+        assert_eq!(buffer.len_lines(), 5);
+        assert_eq!(buffer.line(4), "five");
+    }
+
+    #[test]
+    fn delete_multi_line_text() {
+        let mut buffer = buffer(&["one", "two", "three", "four"]);
+        let removed = buffer.delete_text(pos(0, 1), pos(2, 3));
+        assert_eq!(removed, "ne\ntwo\nthr");
+        assert_eq!(contents(&buffer), ["oee", "four"]);
+        // undone by inserting what was removed
+        buffer.insert_text(pos(0, 1), &removed);
+        assert_eq!(contents(&buffer), ["one", "two", "three", "four"]);
+    }
+
+    #[test]
+    fn remove_the_last_line() {
+        let mut buffer = buffer(&["one", "two"]);
+        assert_eq!(buffer.remove_line(1), "two");
+        assert_eq!(contents(&buffer), ["one"]);
+        // a buffer always keeps one line
+        assert_eq!(buffer.remove_line(0), "one");
+        assert_eq!(contents(&buffer), [""]);
+        assert_eq!(buffer.len_lines(), 1);
+    }
+
+    #[test]
+    fn lines_from_any_offset() {
+        let lines: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let mut buffer = Buffer::from_lines(lines.clone());
+        // inserted lines get random priorities, so the tree is reshaped
+        for i in (0..100).step_by(7) {
+            buffer.insert_line(i, format!("new {}", i));
+            buffer.remove_line(i);
+        }
+        for start in 0..=100 {
+            let from: Vec<&str> = buffer.lines_from(start).collect();
+            assert_eq!(from, lines[start..], "lines from {}", start);
+        }
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(buffer.line(i), line);
+        }
+    }
+}
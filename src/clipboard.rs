@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+/// Register used when none is named, the one mirrored to the system clipboard.
+pub const DEFAULT_REGISTER: char = '"';
+
+/// Copied text, kept per register and shared by all docs.
+pub struct Clipboard {
+    registers: HashMap<char, String>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self {
+            registers: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, register: char, text: String) {
+        self.registers.insert(register, text);
+    }
+
+    pub fn get(&self, register: char) -> Option<&str> {
+        self.registers.get(&register).map(String::as_str)
+    }
+}
+
+/// Escape asking the terminal to put `text` on the system clipboard, which
+/// also works over SSH.
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -1,10 +1,13 @@
+use crate::layout::Axis;
+
 #[derive(Clone, Copy)]
 pub struct Size {
     pub height: usize,
     pub width: usize,
 }
 
-#[derive(Clone, Copy)]
+// ordered by row, then column
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Position {
     pub row: usize,
     pub col: usize,
@@ -14,10 +17,31 @@ pub struct Position {
 pub enum Task {
     SetCommand(String),
     AskInput(String),
+    // like AskInput, Tab completes file paths
+    AskPath(String),
+    AskChoice(String),
     NewDoc,
     None,
     OpenDoc(String),
+    // pick a file to open with the finder
+    FindFile,
     NextTab,
     PrevTab,
     CloseCurrentTab,
+    ToggleFileTree,
+    SwitchFocus,
+    CommandPalette,
+    // show the active doc a second time next to the focused pane
+    SplitPane(Axis),
+    ClosePane,
+    NextPane,
+    PrevPane,
+    GrowPane,
+    ShrinkPane,
+    Quit,
+    // title and text of a new doc with no file behind it
+    ShowDiff(String, String),
+    // register and the text to put in it
+    Copy(char, String),
+    Paste(char),
 }
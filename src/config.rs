@@ -1,7 +1,34 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use termion::event::Key;
+use toml::{Table, Value};
+
+use crate::{
+    highlight::Highlight,
+    keymap::{self, Action, Context, Keymap},
+    theme::Theme,
+};
+
 pub struct General {
     pub line_number_padding_right: usize,
     pub line_number_padding_left: usize,
     pub file_tree_width: usize,
+    pub tab_width: usize,
+    pub line_numbers: bool,
+    // keep the previous version as `file~` when saving
+    pub backup: bool,
+    // also send copied text to the system clipboard with OSC 52
+    pub osc52_clipboard: bool,
+    // modal editing with vim keys
+    pub vim_mode: bool,
+    // long lines continue on the next screen rows instead of scrolling sideways
+    pub soft_wrap: bool,
+    // seconds between writes of swap files for unsaved changes, 0 for none
+    pub swap_interval: usize,
 }
 
 impl General {
@@ -10,18 +37,211 @@ impl General {
             line_number_padding_left: 2,
             line_number_padding_right: 1,
             file_tree_width: 18,
+            tab_width: 4,
+            line_numbers: true,
+            backup: false,
+            osc52_clipboard: false,
+            vim_mode: false,
+            soft_wrap: false,
+            swap_interval: 4,
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        match key {
+            "line_number_padding_left" => self.line_number_padding_left = as_usize(value)?,
+            "line_number_padding_right" => self.line_number_padding_right = as_usize(value)?,
+            "file_tree_width" => self.file_tree_width = as_usize(value)?,
+            "tab_width" => match as_usize(value)? {
+                0 => return Err("must be at least 1".to_string()),
+                width => self.tab_width = width,
+            },
+            "line_numbers" => self.line_numbers = as_bool(value)?,
+            "backup" => self.backup = as_bool(value)?,
+            "osc52_clipboard" => self.osc52_clipboard = as_bool(value)?,
+            "vim_mode" => self.vim_mode = as_bool(value)?,
+            "soft_wrap" => self.soft_wrap = as_bool(value)?,
+            "swap_interval" => self.swap_interval = as_usize(value)?,
+            _ => return Err("unknown key".to_string()),
         }
+        Ok(())
     }
 }
 
 pub struct Config {
     pub general: General,
+    pub theme: Theme,
+    pub keymap: Keymap,
+    // problems found while loading, shown in the status line
+    pub errors: Vec<String>,
+    // recent and swap files are kept there, nothing is kept without one
+    pub state_dir: Option<PathBuf>,
+    // key sequences bound by the file being loaded, to catch clashes
+    file_bindings: HashMap<(Context, Vec<Key>), Action>,
 }
 
 impl Config {
     pub fn new() -> Self {
         Config {
             general: General::new(),
+            theme: Theme::new(),
+            keymap: Keymap::new(),
+            errors: vec![],
+            state_dir: None,
+            file_bindings: HashMap::new(),
+        }
+    }
+
+    /// Defaults overridden by the user config file, then by the nearest
+    /// `.kaelix.toml` of the working directory.
+    pub fn load() -> Self {
+        let mut config = Self::new();
+        config.state_dir = state_dir();
+        let paths = [Self::user_config_path(), Self::project_config_path()];
+        for path in paths.into_iter().flatten() {
+            config.apply_file(&path);
+        }
+        let conflicts = config.keymap.conflicts();
+        config.errors.extend(conflicts);
+        config
+    }
+
+    fn user_config_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("kaelix").join("config.toml"))
+    }
+
+    fn project_config_path() -> Option<PathBuf> {
+        let cwd = env::current_dir().ok()?;
+        cwd.ancestors()
+            .map(|dir| dir.join(".kaelix.toml"))
+            .find(|path| path.is_file())
+    }
+
+    fn apply_file(&mut self, path: &Path) {
+        let Ok(content) = fs::read_to_string(path) else {
+            return;
+        };
+        let name = path.display();
+        self.file_bindings.clear();
+        match content.parse::<Table>() {
+            Ok(table) => self.apply_table(&table, &name.to_string()),
+            Err(err) => self.errors.push(format!(
+                "{}: {}",
+                name,
+                err.message().lines().next().unwrap_or("invalid TOML")
+            )),
+        }
+    }
+
+    fn apply_table(&mut self, table: &Table, source: &str) {
+        for (section, value) in table {
+            let Value::Table(values) = value else {
+                self.errors
+                    .push(format!("{}: `{}` should be a table", source, section));
+                continue;
+            };
+            // a theme name replaces all colors, so it goes before the overrides
+            let mut values: Vec<_> = values.iter().collect();
+            values.sort_by_key(|(key, _)| key.as_str() != "name");
+            for (key, value) in values {
+                let result = match section.as_str() {
+                    "general" => self.general.set(key, value),
+                    "theme" => self.set_theme(key, value),
+                    "keybindings" => self.set_keybinding(key, value),
+                    _ => Err("unknown section".to_string()),
+                };
+                if let Err(err) = result {
+                    self.errors
+                        .push(format!("{}: {}.{}: {}", source, section, key, err));
+                }
+            }
         }
     }
+
+    fn set_theme(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        if key == "name" {
+            let name = as_str(value)?;
+            self.theme = Theme::named(name)
+                .ok_or_else(|| format!("unknown theme, choose from {}", Theme::NAMES.join(", ")))?;
+            return Ok(());
+        }
+        let highlight = Highlight::from_name(key).ok_or("unknown key")?;
+        self.theme.set(highlight, value)
+    }
+
+    /// `action = "keys"` binds in the action's own context, a table named
+    /// after a context binds there. Several sequences go in an array.
+    fn set_keybinding(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        if let (Some(context), Value::Table(table)) = (Context::from_name(key), value) {
+            for (name, value) in table {
+                let action = Action::from_name(name).ok_or("unknown action")?;
+                self.bind(context, action, value)
+                    .map_err(|err| format!("{}: {}", name, err))?;
+            }
+            return Ok(());
+        }
+        let action = Action::from_name(key).ok_or("unknown action")?;
+        self.bind(action.context(), action, value)
+    }
+
+    fn bind(&mut self, context: Context, action: Action, value: &Value) -> Result<(), String> {
+        let specs = match value {
+            Value::Array(values) => values.iter().map(as_str).collect::<Result<Vec<_>, _>>()?,
+            _ => vec![as_str(value)?],
+        };
+        let sequences = specs
+            .into_iter()
+            .map(keymap::parse_keys)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut clashes = vec![];
+        for keys in &sequences {
+            let bound = self.file_bindings.insert((context, keys.clone()), action);
+            if let Some(other) = bound.filter(|other| *other != action) {
+                clashes.push(format!(
+                    "`{}` is also bound to {}",
+                    keymap::format_keys(keys),
+                    other.name()
+                ));
+            }
+        }
+        self.keymap.bind(context, action, sequences);
+        match clashes.is_empty() {
+            true => Ok(()),
+            false => Err(clashes.join(", ")),
+        }
+    }
+}
+
+// where kaelix keeps what it remembers between sessions
+fn state_dir() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?)
+            .join(".local")
+            .join("state"),
+    };
+    Some(dir.join("kaelix"))
+}
+
+fn as_usize(value: &Value) -> Result<usize, String> {
+    match value {
+        Value::Integer(n) => usize::try_from(*n).map_err(|_| "must not be negative".to_string()),
+        _ => Err("expected a number".to_string()),
+    }
+}
+
+fn as_bool(value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| "expected true or false".to_string())
+}
+
+fn as_str(value: &Value) -> Result<&str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| "expected a string".to_string())
 }
@@ -0,0 +1,138 @@
+const CONTEXT: usize = 3;
+// bigger changes are shown as a whole block removed and added
+const MAX_TABLE: usize = 4_000_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Change {
+    Same,
+    Removed,
+    Added,
+}
+
+// one line of the diff, with the old and new line index before it
+#[derive(Clone, Copy)]
+struct Op {
+    change: Change,
+    old: usize,
+    new: usize,
+}
+
+/// Line diff of `old` to `new` in unified format.
+pub fn unified(old: &[&str], new: &[&str], old_name: &str, new_name: &str) -> String {
+    let ops = diff(old, new);
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+
+    let changed: Vec<usize> = (0..ops.len())
+        .filter(|&i| ops[i].change != Change::Same)
+        .collect();
+    let mut idx = 0;
+    while idx < changed.len() {
+        // grow the hunk while the next change is close enough to share context
+        let mut last = idx;
+        while last + 1 < changed.len() && changed[last + 1] - changed[last] <= 2 * CONTEXT {
+            last += 1;
+        }
+        let start = changed[idx].saturating_sub(CONTEXT);
+        let end = (changed[last] + CONTEXT + 1).min(ops.len());
+        let hunk = &ops[start..end];
+
+        let old_len = hunk.iter().filter(|op| op.change != Change::Added).count();
+        let new_len = hunk
+            .iter()
+            .filter(|op| op.change != Change::Removed)
+            .count();
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk[0].old + 1,
+            old_len,
+            hunk[0].new + 1,
+            new_len
+        ));
+        for op in hunk {
+            let line = match op.change {
+                Change::Same => format!(" {}", old[op.old]),
+                Change::Removed => format!("-{}", old[op.old]),
+                Change::Added => format!("+{}", new[op.new]),
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+        idx = last + 1;
+    }
+    out
+}
+
+fn diff(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut changes = vec![Change::Same; prefix];
+    changes.extend(middle(a, b));
+    changes.extend(vec![Change::Same; suffix]);
+
+    let (mut old_idx, mut new_idx) = (0, 0);
+    changes
+        .into_iter()
+        .map(|change| {
+            let op = Op {
+                change,
+                old: old_idx,
+                new: new_idx,
+            };
+            if change != Change::Added {
+                old_idx += 1;
+            }
+            if change != Change::Removed {
+                new_idx += 1;
+            }
+            op
+        })
+        .collect()
+}
+
+// longest common subsequence of the differing middle part
+fn middle(a: &[&str], b: &[&str]) -> Vec<Change> {
+    if a.len().saturating_mul(b.len()) > MAX_TABLE {
+        let mut changes = vec![Change::Removed; a.len()];
+        changes.extend(vec![Change::Added; b.len()]);
+        return changes;
+    }
+    let width = b.len() + 1;
+    // lcs[i * width + j] is the LCS length of a[i..] and b[j..]
+    let mut lcs = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i * width + j] = if a[i] == b[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            changes.push(Change::Same);
+            i += 1;
+            j += 1;
+        } else if j == b.len()
+            || (i < a.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            changes.push(Change::Removed);
+            i += 1;
+        } else {
+            changes.push(Change::Added);
+            j += 1;
+        }
+    }
+    changes
+}
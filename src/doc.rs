@@ -1,57 +1,229 @@
-use std::{cmp::min, ffi::OsStr, fs, io, path::Path};
+use std::{
+    cmp::min,
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-use regex::Regex;
-use std::cmp::max;
 use termion::{
-    color,
     event::{Key, MouseEvent},
     style,
 };
 
 use crate::{
+    buffer::Buffer,
+    clipboard::DEFAULT_REGISTER,
     common::Position,
     common::{Size, Task},
-    config::Config,
+    config::{Config, General},
+    diff,
+    fileio::{self, DiskChange, DiskState},
+    format::FileFormat,
+    highlight::{Highlight, Span},
+    history::{Edit, EditKind, History},
+    keymap::Action,
+    layout::Axis,
+    search::{Match, Replace, Search},
+    swap::{self, Leftover},
+    syntax::{Highlighter, Syntax},
+    text,
+    vim::{self, Command, InsertAt, Mode, Motion, Operator, Target, Vim},
 };
 
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+// rows moved by one step of the mouse wheel
+const SCROLL_ROWS: usize = 3;
+// a counted paste repeats the text up to this size
+const MAX_PASTE_BYTES: usize = 16 * 1024 * 1024;
+// narrower views leave out the line numbers to keep some text in sight
+const MIN_TEXT_WIDTH: usize = 8;
+
 enum TaskPending {
     SaveFile,
     None,
-    OpenDoc,
+    Search,
+    ReplacePattern,
+    ReplaceWith,
+    ReplaceConfirm,
+    CloseConfirm,
+    DiskChanged,
+    DiskDeleted,
+    SwapFound,
+    CopyRegister,
+    PasteRegister,
+    VimCommand,
+}
+
+/// Where a pane showing the doc looks at it. The doc holds the view of the
+/// focused pane, the other panes keep theirs aside.
+#[derive(Clone, Copy)]
+pub struct DocView {
+    cursor_pos: Position,
+    selection_anchor: Option<Position>,
+    offset: Position,
+    view_height: usize,
+    view_width: usize,
+    wrap_width: Option<usize>,
+    wrap_offset: usize,
 }
 
 pub struct Doc {
-    pub lines: Vec<String>,
+    buffer: Buffer,
     pub cursor_pos: Position,
+    // the other end of the selection, which runs up to the cursor
+    selection_anchor: Option<Position>,
+    vim: Vim,
+    // where and how often the next paste goes, when asked for by vim's p and P
+    vim_paste: Option<(bool, usize)>,
+    // time and place of the last click, to notice double clicks
+    last_click: Option<(Instant, Position)>,
     pub offset: Position,
+    // rows of text and cells of the line numbers and text shown by the
+    // last render
+    view_height: usize,
+    view_width: usize,
+    // text width lines are wrapped at, set on render when soft wrap is on
+    wrap_width: Option<usize>,
+    // first screen row of line offset.row shown when wrapping
+    wrap_offset: usize,
+    // settings of the config, set on render
+    tab_width: usize,
+    // keep the previous version as `file~` when saving
+    backup: bool,
     file_path: Option<String>,
+    // shown instead of a file name for docs not backed by a file
+    title: Option<String>,
+    // the file as last loaded or saved, to notice changes made by others
+    disk_state: Option<DiskState>,
     task_pending: TaskPending,
     pub command_status: String,
+    history: History,
+    search: Option<Search>,
+    // cursor when the search prompt was opened, restored on cancel
+    search_origin: Position,
+    replace: Option<Replace>,
+    highlighter: Highlighter,
+    format: FileFormat,
+    // history state and format the file on disk matches
+    saved_state: u64,
+    saved_format: FileFormat,
+    close_after_save: bool,
+    // written while there are unsaved changes, with the history state and
+    // format it holds
+    swap_file: Option<PathBuf>,
+    swapped: Option<(u64, FileFormat)>,
+    // left by an editor that died, while asking what to do with it
+    swap_found: Option<Leftover>,
 }
 
 impl Doc {
     pub fn new() -> Self {
-        let lines = vec!["".to_string()];
         Self {
-            lines,
+            buffer: Buffer::new(),
             cursor_pos: Position { row: 0, col: 0 },
+            selection_anchor: None,
+            vim: Vim::new(),
+            vim_paste: None,
+            last_click: None,
             offset: Position { row: 0, col: 0 },
+            view_height: 0,
+            view_width: 0,
+            wrap_width: None,
+            wrap_offset: 0,
+            tab_width: General::new().tab_width,
+            backup: General::new().backup,
             file_path: None,
+            title: None,
+            disk_state: None,
             task_pending: TaskPending::None,
             command_status: "Untitled file".to_string(),
+            history: History::new(),
+            search: None,
+            search_origin: Position { row: 0, col: 0 },
+            replace: None,
+            highlighter: Highlighter::new(None),
+            format: FileFormat::new(),
+            saved_state: 0,
+            saved_format: FileFormat::new(),
+            close_after_save: false,
+            swap_file: None,
+            swapped: None,
+            swap_found: None,
         }
     }
 
+    pub fn get_view(&self) -> DocView {
+        DocView {
+            cursor_pos: self.cursor_pos,
+            selection_anchor: self.selection_anchor,
+            offset: self.offset,
+            view_height: self.view_height,
+            view_width: self.view_width,
+            wrap_width: self.wrap_width,
+            wrap_offset: self.wrap_offset,
+        }
+    }
+
+    /// Takes over the view of another pane, kept inside the text in case it
+    /// changed since.
+    pub fn set_view(&mut self, view: DocView) {
+        self.cursor_pos = self.clamp_pos(view.cursor_pos);
+        self.selection_anchor = view.selection_anchor.map(|pos| self.clamp_pos(pos));
+        self.offset = Position {
+            row: view.offset.row.min(self.line_count() - 1),
+            col: view.offset.col,
+        };
+        self.view_height = view.view_height;
+        self.view_width = view.view_width;
+        self.wrap_width = view.wrap_width;
+        self.wrap_offset = view.wrap_offset;
+    }
+
+    fn clamp_pos(&self, pos: Position) -> Position {
+        let row = pos.row.min(self.line_count() - 1);
+        Position {
+            row,
+            col: pos.col.min(self.line_cols(row)),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.buffer.len_lines()
+    }
+
     pub fn get_line_number_length(&self) -> usize {
-        self.lines.len().to_string().len()
+        self.line_count().to_string().len()
+    }
+
+    pub fn get_gutter_width(&self, config: &Config) -> usize {
+        if !config.general.line_numbers {
+            return 0;
+        }
+        let width = config.general.line_number_padding_left
+            + self.get_line_number_length()
+            + config.general.line_number_padding_right;
+        match self.view_width < width + MIN_TEXT_WIDTH {
+            true => 0,
+            false => width,
+        }
     }
 
     pub fn render(&mut self, size: &Size, config: &Config) -> Vec<String> {
-        let line_number_width = self.get_line_number_length();
-        let content_width =
-            size.width - line_number_width - config.general.line_number_padding_right;
+        self.view_width = size.width;
+        let content_width = size.width - self.get_gutter_width(config);
+        self.view_height = size.height;
+        self.tab_width = config.general.tab_width;
+        self.backup = config.general.backup;
+        self.wrap_width = config.general.soft_wrap.then_some(content_width);
+        if self.wrap_width.is_some() {
+            return self.render_wrapped(size.height, content_width, config);
+        }
 
-        let rows_to_render = min(size.height, self.lines.len() - self.offset.row);
+        let rows_to_render = min(
+            size.height,
+            self.line_count().saturating_sub(self.offset.row),
+        );
         let cols_to_render = content_width;
 
         let mut frames = Vec::with_capacity(rows_to_render);
@@ -60,70 +232,203 @@ impl Doc {
 
         let line_indexs_to_render = self.offset.row..(self.offset.row + rows_to_render);
 
-        for (line, i) in self.lines[line_indexs_to_render.clone()]
-            .iter()
+        for (line, i) in self
+            .buffer
+            .lines_from(self.offset.row)
             .zip(line_indexs_to_render)
         {
-            let index = i + 1;
-            let sub_line = Self::sub_string(line, self.offset.col, cols_to_render);
-            // was creating error when ☆ was in string
-            // assert!(sub_line.len() <= cols_to_render);
+            let mut spans = self.highlighter.spans(&self.buffer, i, line);
+            spans.append(&mut self.get_search_spans(i, line));
+            spans.extend(self.get_selection_span(i, line));
+            let sub_line = text::sub_cells(
+                line,
+                self.offset.col,
+                cols_to_render,
+                &spans,
+                &config.theme,
+                self.tab_width,
+            );
+            // padded so panes to the right start in the same column
+            let shown = cols_to_render
+                .min(text::width(line, self.tab_width).saturating_sub(self.offset.col));
             frames.push(format!(
-                "{}{}{}{}{}{}",
-                " ".repeat(line_number_width - index.to_string().len()),
-                color::Fg(color::Green),
-                index,
-                style::Reset,
-                " ".repeat(config.general.line_number_padding_right),
+                "{}{}{}",
+                self.render_gutter(Some(i + 1), config),
                 sub_line,
+                " ".repeat(cols_to_render - shown),
             ));
         }
         frames
     }
 
-    fn sub_string(str: &str, start: usize, len: usize) -> String {
-        let mut result = String::with_capacity(min(len, str.len().saturating_sub(start)));
+    // line number, or the continuation marker of a wrapped line for `None`
+    fn render_gutter(&self, number: Option<usize>, config: &Config) -> String {
+        if self.get_gutter_width(config) == 0 {
+            return String::new();
+        }
+        let label = match number {
+            Some(number) => number.to_string(),
+            None => "↪".to_string(),
+        };
+        format!(
+            "{}{}{}{}{}",
+            " ".repeat(
+                config.general.line_number_padding_left + self.get_line_number_length()
+                    - text::width(&label, self.tab_width)
+            ),
+            config.theme.escape(Highlight::LineNumber),
+            label,
+            style::Reset,
+            " ".repeat(config.general.line_number_padding_right),
+        )
+    }
+
+    fn render_wrapped(
+        &mut self,
+        height: usize,
+        content_width: usize,
+        config: &Config,
+    ) -> Vec<String> {
+        self.update_wrapped_offset(height);
+        let mut frames = Vec::with_capacity(height);
+        let (mut row, mut sub) = (self.offset.row, self.wrap_offset);
+        while frames.len() < height && row < self.line_count() {
+            let starts = self.row_starts(row);
+            let line = self.buffer.line(row);
+            let mut spans = self.highlighter.spans(&self.buffer, row, line);
+            spans.append(&mut self.get_search_spans(row, line));
+            spans.extend(self.get_selection_span(row, line));
+            for (i, start) in starts.iter().enumerate().skip(sub) {
+                if frames.len() == height {
+                    break;
+                }
+                let start_cell = text::cells_before(line, *start, self.tab_width);
+                let end_cell = starts
+                    .get(i + 1)
+                    .map_or(text::width(line, self.tab_width), |next| {
+                        text::cells_before(line, *next, self.tab_width)
+                    });
+                let sub_line = text::sub_cells(
+                    line,
+                    start_cell,
+                    end_cell - start_cell,
+                    &spans,
+                    &config.theme,
+                    self.tab_width,
+                );
+                let number = (i == 0).then_some(row + 1);
+                frames.push(format!(
+                    "{}{}{}",
+                    self.render_gutter(number, config),
+                    sub_line,
+                    " ".repeat(content_width.saturating_sub(end_cell - start_cell)),
+                ));
+            }
+            row += 1;
+            sub = 0;
+        }
+        frames
+    }
+
+    fn get_search_spans(&self, row: usize, line: &str) -> Vec<Span> {
+        let mut spans = vec![];
+        if let Some(search) = &self.search {
+            let cursor = self.byte_pos(self.cursor_pos);
+            for range in search.matches_in_line(line) {
+                let highlight = if row == cursor.row && range.start == cursor.col {
+                    Highlight::CurrentMatch
+                } else {
+                    Highlight::Match
+                };
+                spans.push(Span::new(range, highlight));
+            }
+        }
+        spans
+    }
 
-        for ch in str.as_bytes().iter().skip(start).take(len) {
-            let ch = char::try_from(ch.to_owned()).unwrap();
-            result.push(ch);
+    fn get_selection_span(&self, row: usize, line: &str) -> Option<Span> {
+        let (start, end) = self.get_selection()?;
+        if row < start.row || row > end.row {
+            return None;
         }
+        let from = if row == start.row {
+            self.byte_pos(start).col
+        } else {
+            0
+        };
+        let to = if row == end.row {
+            self.byte_pos(end).col
+        } else {
+            line.len()
+        };
+        Some(Span::new(from..to, Highlight::Selection))
+    }
+
+    /// Number of grapheme columns in line `row`.
+    fn line_cols(&self, row: usize) -> usize {
+        text::grapheme_count(self.buffer.line(row))
+    }
 
-        result
+    /// Screen cell of the cursor inside the line, before horizontal scrolling.
+    pub fn get_cursor_cell(&self) -> usize {
+        text::cells_before(
+            self.buffer.line(self.cursor_pos.row),
+            self.cursor_pos.col,
+            self.tab_width,
+        )
     }
 
     pub(crate) fn open(path: &str) -> Option<Self> {
         if let Ok(file) = fs::read_to_string(path) {
             // File exists
-            let mut file = Self::split_file(&file);
-            if file.is_empty() {
-                file.push("");
-            }
-            Some(Self {
-                lines: file.iter().map(|row| row.to_string()).collect(),
-                cursor_pos: Position { row: 0, col: 0 },
-                offset: Position { row: 0, col: 0 },
-                file_path: Some(path.to_string()),
-                task_pending: TaskPending::None,
-                command_status: "File loaded succesfully".to_string(),
-            })
+            let (format, lines) = FileFormat::detect(&file);
+            let mut doc = Self::new();
+            doc.buffer = Buffer::from_lines(lines.iter().map(|row| row.to_string()).collect());
+            doc.format = format;
+            doc.saved_format = format;
+            doc.set_file_path(path.to_string());
+            doc.disk_state = DiskState::of(path, file.as_bytes());
+            doc.command_status = "File loaded succesfully".to_string();
+            Some(doc)
         } else {
             None
         }
     }
-    pub fn split_file(contents: &str) -> Vec<&str> {
-        // Detect DOS line ending
-        let splitter = Regex::new("(?ms)(\r\n|\n)").unwrap();
-        splitter.split(contents).collect()
+    /// Generated text with no file behind it, such as a diff.
+    pub(crate) fn scratch(title: String, content: &str) -> Self {
+        let (_, lines) = FileFormat::detect(content);
+        let mut doc = Self::new();
+        doc.buffer = Buffer::from_lines(lines.iter().map(|row| row.to_string()).collect());
+        doc.title = Some(title);
+        doc.command_status = String::new();
+        doc
+    }
+    pub fn is_dirty(&self) -> bool {
+        self.history.get_state_id() != self.saved_state || self.format != self.saved_format
     }
 
     pub(crate) fn can_close(&self) -> bool {
-        true
+        !self.is_dirty()
     }
 }
 
 impl Doc {
+    /// In vim mode keys go to vim first, `None` leaves the key to the keymap.
+    pub fn process_vim_key(&mut self, key: &Key) -> Option<Task> {
+        self.vim_paste = None;
+        let command = self.vim.process_key(key)?;
+        self.history.seal();
+        Some(self.run_vim_command(command))
+    }
+
+    /// Keys not bound to an action: typing and cursor movement.
     pub fn process_key(&mut self, key: &Key) -> Task {
+        if !matches!(key, Key::Char(_) | Key::Backspace) {
+            self.history.seal();
+        }
+        if matches!(key, Key::Left | Key::Right | Key::Up | Key::Down) {
+            self.selection_anchor = None;
+        }
         match key {
             Key::Left => {
                 self.col_left();
@@ -143,70 +448,202 @@ impl Doc {
             }
             Key::Char(ch) => self.write_char(ch.to_owned()),
             Key::Backspace => self.handle_backspace(),
-            Key::Ctrl('s') => self.process_save_file(),
-            Key::Ctrl('n') => Task::NewDoc,
-            Key::Ctrl('o') => {
-                self.task_pending = TaskPending::OpenDoc;
-                Task::AskInput("Doc path".to_string())
-            }
-            Key::Ctrl('k') => Task::PrevTab,
-            Key::Ctrl('l') => Task::NextTab,
-            Key::Ctrl('w') => Task::CloseCurrentTab,
             _ => Task::None,
         }
     }
 
+    /// Moves the cursor with Shift held, growing the selection.
+    pub fn process_shift_key(&mut self, key: &Key) -> Task {
+        self.history.seal();
+        if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(self.cursor_pos);
+        }
+        match key {
+            Key::Left => self.col_left(),
+            Key::Right => self.col_right(),
+            Key::Up => self.row_up(),
+            Key::Down => self.row_down(),
+            _ => {}
+        }
+        Task::None
+    }
+
+    pub fn process_action(&mut self, action: Action) -> Task {
+        self.vim_paste = None;
+        self.history.seal();
+        match action {
+            Action::Save => self.process_save_file(),
+            Action::NewDoc => Task::NewDoc,
+            Action::OpenDoc => Task::FindFile,
+            Action::PrevTab => Task::PrevTab,
+            Action::NextTab => Task::NextTab,
+            Action::CloseTab => self.request_close(),
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::Copy => self.copy(DEFAULT_REGISTER),
+            Action::Cut => self.cut(),
+            Action::Paste => Task::Paste(DEFAULT_REGISTER),
+            Action::CopyToRegister => {
+                self.task_pending = TaskPending::CopyRegister;
+                Task::AskChoice("Copy to register".to_string())
+            }
+            Action::PasteFromRegister => {
+                self.task_pending = TaskPending::PasteRegister;
+                Task::AskChoice("Paste from register".to_string())
+            }
+            Action::Find => self.start_search(),
+            Action::FindNext => self.search_next(true),
+            Action::FindPrevious => self.search_next(false),
+            Action::Replace => {
+                self.task_pending = TaskPending::ReplacePattern;
+                Task::AskInput("Replace regex".to_string())
+            }
+            Action::ClearSearch => {
+                self.search = None;
+                Task::None
+            }
+            Action::ToggleLineEnding => {
+                self.format.line_ending = self.format.line_ending.toggled();
+                self.command_status =
+                    format!("Line endings set to {}", self.format.line_ending.name());
+                Task::None
+            }
+            Action::ToggleFileTree => Task::ToggleFileTree,
+            Action::SwitchFocus => Task::SwitchFocus,
+            Action::CommandPalette => Task::CommandPalette,
+            Action::SplitRight => Task::SplitPane(Axis::Horizontal),
+            Action::SplitDown => Task::SplitPane(Axis::Vertical),
+            Action::ClosePane => Task::ClosePane,
+            Action::NextPane => Task::NextPane,
+            Action::PrevPane => Task::PrevPane,
+            Action::GrowPane => Task::GrowPane,
+            Action::ShrinkPane => Task::ShrinkPane,
+            // handled by the editor
+            Action::Quit => Task::Quit,
+            // not bound in docs by default
+            Action::TreeUp
+            | Action::TreeDown
+            | Action::TreeFirst
+            | Action::TreeLast
+            | Action::TreeExpand
+            | Action::TreeCollapse
+            | Action::TreeOpen
+            | Action::TreeRefresh
+            | Action::PromptSubmit
+            | Action::PromptCancel
+            | Action::PromptLeft
+            | Action::PromptRight
+            | Action::PromptBackspace
+            | Action::PromptUp
+            | Action::PromptDown
+            | Action::PromptHome
+            | Action::PromptEnd
+            | Action::PromptDeleteWord
+            | Action::PromptComplete => Task::None,
+        }
+    }
+
     fn col_left(&mut self) {
-        if self.cursor_pos.col > 0 {
-            self.cursor_pos.col = self.cursor_pos.col.saturating_sub(1);
-        } else if self.cursor_pos.row > 0 {
-            self.cursor_pos.row = self.cursor_pos.row.saturating_sub(1);
-            self.cursor_pos.col = max(self.lines[self.cursor_pos.row].len(), 0);
+        self.cursor_pos = self.prev_pos(self.cursor_pos);
+    }
+
+    // position one grapheme to the right, wrapping to the start of the next line
+    fn next_pos(&self, pos: Position) -> Position {
+        if pos.col < self.line_cols(pos.row) {
+            Position {
+                row: pos.row,
+                col: pos.col + 1,
+            }
+        } else if pos.row + 1 < self.line_count() {
+            Position {
+                row: pos.row + 1,
+                col: 0,
+            }
+        } else {
+            pos
+        }
+    }
+
+    // position one grapheme to the left, wrapping to the end of the previous line
+    fn prev_pos(&self, pos: Position) -> Position {
+        if pos.col > 0 {
+            Position {
+                row: pos.row,
+                col: pos.col - 1,
+            }
+        } else if pos.row > 0 {
+            Position {
+                row: pos.row - 1,
+                col: self.line_cols(pos.row - 1),
+            }
+        } else {
+            pos
         }
     }
 
     fn col_right(&mut self) {
-        if self.cursor_pos.col < self.lines[self.cursor_pos.row].len() {
+        if self.cursor_pos.col < self.line_cols(self.cursor_pos.row) {
             self.cursor_pos.col = self.cursor_pos.col.saturating_add(1);
-        } else if self.cursor_pos.row + 1 < self.lines.len() {
+        } else if self.cursor_pos.row + 1 < self.line_count() {
             self.cursor_pos.row = self.cursor_pos.row.saturating_add(1);
             self.cursor_pos.col = 0;
         }
     }
 
     fn row_up(&mut self) {
+        if self.wrap_width.is_some() {
+            return self.move_screen_row(true);
+        }
         if self.cursor_pos.row > 0 {
-            self.cursor_pos.row = self.cursor_pos.row.saturating_sub(1);
-            if self.lines[self.cursor_pos.row].len() <= self.cursor_pos.col {
-                self.cursor_pos.col = max(self.lines[self.cursor_pos.row].len(), 0);
-            }
+            self.move_to_row(self.cursor_pos.row - 1);
         }
     }
 
     fn row_down(&mut self) {
-        if self.cursor_pos.row + 1 < self.lines.len() {
-            self.cursor_pos.row = self.cursor_pos.row.saturating_add(1);
-            if self.lines[self.cursor_pos.row].len() <= self.cursor_pos.col {
-                self.cursor_pos.col = max(self.lines[self.cursor_pos.row].len(), 0);
-            }
+        if self.wrap_width.is_some() {
+            return self.move_screen_row(false);
+        }
+        if self.cursor_pos.row + 1 < self.line_count() {
+            self.move_to_row(self.cursor_pos.row + 1);
         }
     }
 
+    // keeps the cursor on the same screen cell, so moving across wide
+    // characters doesn't drift sideways
+    fn move_to_row(&mut self, row: usize) {
+        let cell = self.get_cursor_cell();
+        self.cursor_pos.row = row;
+        self.cursor_pos.col = text::col_at_cell(self.buffer.line(row), cell, self.tab_width);
+    }
+
+    // offset.col is counted in screen cells
     fn update_offset(&mut self, render_nrows: usize, render_ncols: usize) {
-        let last_row = self.offset.row + render_nrows - 1;
-        let last_col = self.offset.col + render_ncols - 1;
+        // an empty view still keeps the cursor row and cell in sight
+        let last_row = self.offset.row + render_nrows.max(1) - 1;
+        let last_col = self.offset.col + render_ncols.max(1) - 1;
+
+        let cursor_cell = self.get_cursor_cell();
+        // a wide character under the cursor has to fit completely
+        let cursor_width = text::cells_before(
+            self.buffer.line(self.cursor_pos.row),
+            self.cursor_pos.col + 1,
+            self.tab_width,
+        )
+        .saturating_sub(cursor_cell)
+        .max(1);
+        let cursor_last_cell = cursor_cell + cursor_width - 1;
 
         if last_row < self.cursor_pos.row {
             self.offset.row += self.cursor_pos.row - last_row;
         }
-        if last_col < self.cursor_pos.col {
-            self.offset.col += self.cursor_pos.col - last_col;
+        if last_col < cursor_last_cell {
+            self.offset.col += cursor_last_cell - last_col;
         }
         if self.cursor_pos.row < self.offset.row {
             self.offset.row = self.cursor_pos.row;
         }
-        if self.cursor_pos.col < self.offset.col {
-            self.offset.col = self.cursor_pos.col;
+        if cursor_cell < self.offset.col {
+            self.offset.col = cursor_cell;
         }
     }
 }
@@ -214,65 +651,205 @@ impl Doc {
 // doc edit operations
 impl Doc {
     pub fn set_file_path(&mut self, path: String) {
+        self.highlighter = Highlighter::new(Syntax::for_path(&path));
         self.file_path = Some(path);
+        self.disk_state = None;
     }
     fn write_char(&mut self, ch: char) -> Task {
-        if ch == '\n' {
-            let mut new_string = self.lines.remove(self.cursor_pos.row);
-            new_string.insert(self.cursor_pos.col, '\n');
+        if self.get_selection().is_some() {
+            self.replace_selection(&ch.to_string());
+            return Task::None;
+        }
+        let kind = if ch == '\n' {
+            EditKind::Other
+        } else {
+            EditKind::Typing
+        };
+        self.insert_text(self.cursor_pos, &ch.to_string(), kind);
+        Task::None
+    }
+    fn handle_backspace(&mut self) -> Task {
+        if self.delete_selection().is_some() {
+            return Task::None;
+        }
+        let start = self.prev_pos(self.cursor_pos);
+        if start.row != self.cursor_pos.row || start.col != self.cursor_pos.col {
+            self.delete_text(start, self.cursor_pos, EditKind::Deleting);
+        }
+        Task::None
+    }
+
+    /// Inserts `text` at grapheme position `pos`, records it for undo and
+    /// leaves the cursor right after it.
+    pub(crate) fn insert_text(&mut self, pos: Position, text: &str, kind: EditKind) {
+        self.selection_anchor = None;
+        let cursor_before = self.cursor_pos;
+        let byte_pos = self.byte_pos(pos);
+        let end = self.buffer.insert_text(byte_pos, text);
+        self.highlighter.invalidate(pos.row);
+        self.cursor_pos = self.grapheme_pos(end);
+        self.history.record(
+            Edit::Insert {
+                pos: byte_pos,
+                text: text.to_string(),
+            },
+            kind,
+            cursor_before,
+            self.cursor_pos,
+        );
+    }
 
-            let mut new_lines = Self::split_file(&new_string);
-            assert_eq!(2, new_lines.len(), "Doc::split_file should return 2 lines");
+    /// Removes the text between grapheme positions `start` and `end`, records
+    /// it for undo and leaves the cursor at `start`.
+    pub(crate) fn delete_text(&mut self, start: Position, end: Position, kind: EditKind) -> String {
+        self.selection_anchor = None;
+        let cursor_before = self.cursor_pos;
+        let byte_start = self.byte_pos(start);
+        let removed = self.buffer.delete_text(byte_start, self.byte_pos(end));
+        self.highlighter.invalidate(start.row);
+        self.cursor_pos = start;
+        self.history.record(
+            Edit::Delete {
+                pos: byte_start,
+                text: removed.clone(),
+            },
+            kind,
+            cursor_before,
+            self.cursor_pos,
+        );
+        removed
+    }
 
-            self.lines
-                .insert(self.cursor_pos.row, new_lines.remove(0).to_string());
-            self.lines
-                .insert(self.cursor_pos.row + 1, new_lines.remove(0).to_string());
+    fn byte_pos(&self, pos: Position) -> Position {
+        Position {
+            row: pos.row,
+            col: text::byte_index(self.buffer.line(pos.row), pos.col),
+        }
+    }
 
-            self.cursor_pos.row += 1;
-            self.cursor_pos.col = 0;
-        } else {
-            self.lines[self.cursor_pos.row].insert(self.cursor_pos.col, ch);
-            self.col_right();
+    fn grapheme_pos(&self, pos: Position) -> Position {
+        Position {
+            row: pos.row,
+            col: text::grapheme_count(&self.buffer.line(pos.row)[..pos.col]),
+        }
+    }
+
+    fn undo(&mut self) -> Task {
+        match self.history.pop_undo() {
+            Some(transaction) => {
+                for edit in transaction.edits.iter().rev() {
+                    self.apply_edit(edit, true);
+                }
+                self.cursor_pos = transaction.cursor_before;
+                self.command_status = "Undo".to_string();
+            }
+            None => self.command_status = "Nothing to undo".to_string(),
         }
         Task::None
     }
-    fn handle_backspace(&mut self) -> Task {
-        if self.cursor_pos.col == 0 {
-            // merge self.cursor_pos.row-1 and self.cursor_pos.row
-            if self.cursor_pos.row > 0 {
-                let new_col = self.lines[self.cursor_pos.row - 1].len();
-                let curr_line = self.lines.remove(self.cursor_pos.row);
-                self.lines[self.cursor_pos.row - 1].push_str(curr_line.as_str());
 
-                self.cursor_pos.row -= 1;
-                self.cursor_pos.col = new_col;
+    fn redo(&mut self) -> Task {
+        match self.history.pop_redo() {
+            Some(transaction) => {
+                for edit in transaction.edits.iter() {
+                    self.apply_edit(edit, false);
+                }
+                self.cursor_pos = transaction.cursor_after;
+                self.command_status = "Redo".to_string();
             }
-        } else {
-            self.col_left();
-            self.lines[self.cursor_pos.row].remove(self.cursor_pos.col);
+            None => self.command_status = "Nothing to redo".to_string(),
         }
         Task::None
     }
+
+    // applies a recorded edit, or its inverse when `reverse` is set
+    fn apply_edit(&mut self, edit: &Edit, reverse: bool) {
+        self.selection_anchor = None;
+        match edit {
+            Edit::Insert { pos, .. } | Edit::Delete { pos, .. } => {
+                self.highlighter.invalidate(pos.row)
+            }
+        }
+        match (edit, reverse) {
+            (Edit::Insert { pos, text }, false) | (Edit::Delete { pos, text }, true) => {
+                self.buffer.insert_text(*pos, text);
+            }
+            (Edit::Insert { pos, text }, true) | (Edit::Delete { pos, text }, false) => {
+                self.buffer.delete_text(*pos, Edit::end(*pos, text));
+            }
+        }
+    }
+
     fn process_save_file(&mut self) -> Task {
-        match self.file_path.as_ref() {
-            Some(file_path) => match self.save_file(file_path) {
+        // a deleted file is simply written back
+        if self.changed_on_disk() == Some(DiskChange::Modified) {
+            return self.ask_disk_change(DiskChange::Modified);
+        }
+        self.write_file()
+    }
+    fn write_file(&mut self) -> Task {
+        match self.file_path.clone() {
+            Some(file_path) => match self.save_file(&file_path) {
                 Ok(_) => {
+                    self.disk_state = DiskState::read(&file_path);
+                    self.saved_state = self.history.get_state_id();
+                    self.saved_format = self.format;
+                    self.remove_swap();
                     self.command_status = format!("File saved: {}", file_path);
+                    if self.close_after_save {
+                        self.close_after_save = false;
+                        return Task::CloseCurrentTab;
+                    }
                     Task::SetCommand(format!("File saved: {}", file_path))
                 }
                 Err(err) => {
-                    self.command_status = format!("Unable to save file: {}", err);
+                    self.close_after_save = false;
+                    self.command_status = format!("Unable to save {}: {}", file_path, err);
                     Task::None
                 }
             },
             None => {
                 // ask for file path from command line
                 self.task_pending = TaskPending::SaveFile;
-                Task::AskInput("File path".to_string())
+                Task::AskPath("File path".to_string())
             }
         }
     }
+    fn request_close(&mut self) -> Task {
+        if self.can_close() {
+            return Task::CloseCurrentTab;
+        }
+        self.task_pending = TaskPending::CloseConfirm;
+        Task::AskChoice(format!(
+            "{} has unsaved changes. (s)ave (d)iscard (c)ancel",
+            self.get_title()
+        ))
+    }
+    fn confirm_close(&mut self, choice: &str) -> Task {
+        match choice {
+            "s" => {
+                self.close_after_save = true;
+                self.process_save_file()
+            }
+            "d" => Task::CloseCurrentTab,
+            _ => {
+                self.command_status = "Close cancelled".to_string();
+                Task::None
+            }
+        }
+    }
+    /// Saves to the known path, for saving all docs before quitting.
+    pub(crate) fn save(&mut self) -> bool {
+        self.file_path.is_some()
+            && self.changed_on_disk() != Some(DiskChange::Modified)
+            && matches!(self.write_file(), Task::SetCommand(_))
+    }
+    pub fn get_file_path(&self) -> Option<&str> {
+        self.file_path.as_deref()
+    }
+    pub fn get_language(&self) -> &'static str {
+        self.highlighter.get_name().unwrap_or("Plain text")
+    }
     pub fn get_title(&self) -> String {
         match &self.file_path {
             Some(path) => Path::new(path)
@@ -281,38 +858,247 @@ impl Doc {
                 .to_str()
                 .unwrap_or(path)
                 .to_string(),
-            None => "[No name]".to_string(),
+            None => self
+                .title
+                .clone()
+                .unwrap_or_else(|| "[No name]".to_string()),
         }
     }
+    pub fn get_line_ending(&self) -> &'static str {
+        self.format.line_ending.name()
+    }
     fn get_doc_content(&self) -> String {
-        let mut content = String::new();
-
-        for line in self.lines.iter() {
-            content.push_str(line);
-            content.push('\n');
-        }
-        content
+        self.format.write(self.buffer.lines())
     }
     fn save_file(&self, path: &str) -> io::Result<()> {
         let content = self.get_doc_content();
 
-        fs::write(path, content)
+        fileio::write_atomic(path, content.as_bytes(), self.backup)
+    }
+}
+
+// soft wrap, where a line takes as many screen rows as it needs
+impl Doc {
+    /// Where the cursor shows, relative to the top left of the text area.
+    pub fn get_cursor_view_pos(&self) -> Position {
+        let cursor_cell = self.get_cursor_cell();
+        let Some(width) = self.wrap_width else {
+            return Position {
+                row: self.cursor_pos.row - self.offset.row,
+                col: cursor_cell - self.offset.col,
+            };
+        };
+        let sub = self.sub_row(self.cursor_pos);
+        let rows_above: usize = (self.offset.row..self.cursor_pos.row)
+            .map(|row| self.row_starts(row).len())
+            .sum();
+        let start = self.row_starts(self.cursor_pos.row)[sub];
+        let start_cell =
+            text::cells_before(self.buffer.line(self.cursor_pos.row), start, self.tab_width);
+        Position {
+            row: (rows_above + sub).saturating_sub(self.wrap_offset),
+            // the end of a full row has no cell of its own
+            col: (cursor_cell - start_cell).min(width.saturating_sub(1)),
+        }
+    }
+
+    // columns where the screen rows of line `row` start
+    fn row_starts(&self, row: usize) -> Vec<usize> {
+        match self.wrap_width {
+            Some(width) => text::wrap_cols(self.buffer.line(row), width, self.tab_width),
+            None => vec![0],
+        }
+    }
+
+    // screen row of `pos` within its line
+    fn sub_row(&self, pos: Position) -> usize {
+        self.row_starts(pos.row)
+            .iter()
+            .rposition(|start| *start <= pos.col)
+            .unwrap_or(0)
+    }
+
+    // line and screen row within it `n` rows above `from`, stops at the top
+    fn rows_up(&self, from: (usize, usize), n: usize) -> (usize, usize) {
+        let (mut row, mut sub) = from;
+        for _ in 0..n {
+            if sub > 0 {
+                sub -= 1;
+            } else if row > 0 {
+                row -= 1;
+                sub = self.row_starts(row).len() - 1;
+            } else {
+                break;
+            }
+        }
+        (row, sub)
+    }
+
+    // line and screen row within it `n` rows below `from`, stops at the bottom
+    fn rows_down(&self, from: (usize, usize), n: usize) -> (usize, usize) {
+        let (mut row, mut sub) = from;
+        for _ in 0..n {
+            if sub + 1 < self.row_starts(row).len() {
+                sub += 1;
+            } else if row + 1 < self.line_count() {
+                row += 1;
+                sub = 0;
+            } else {
+                break;
+            }
+        }
+        (row, sub)
+    }
+
+    // column at `cell` of screen row `sub` of line `row`, never past the row
+    fn col_in_screen_row(&self, row: usize, sub: usize, cell: usize) -> usize {
+        let line = self.buffer.line(row);
+        let starts = self.row_starts(row);
+        let start = starts[sub.min(starts.len() - 1)];
+        let start_cell = text::cells_before(line, start, self.tab_width);
+        let col = text::col_at_cell(line, start_cell + cell, self.tab_width);
+        // the first column of the next row would show the cursor there
+        match starts.get(sub + 1) {
+            Some(next) => col.min(next - 1),
+            None => col,
+        }
+    }
+
+    // Up and Down go by screen row, keeping the cursor cell within the row
+    fn move_screen_row(&mut self, up: bool) {
+        let from = (self.cursor_pos.row, self.sub_row(self.cursor_pos));
+        let to = match up {
+            true => self.rows_up(from, 1),
+            false => self.rows_down(from, 1),
+        };
+        if to == from {
+            return;
+        }
+        let cell = self.get_cursor_view_pos().col;
+        self.cursor_pos = Position {
+            row: to.0,
+            col: self.col_in_screen_row(to.0, to.1, cell),
+        };
+    }
+
+    // scrolls just enough to show the cursor
+    fn update_wrapped_offset(&mut self, height: usize) {
+        self.offset.col = 0;
+        let cursor = (self.cursor_pos.row, self.sub_row(self.cursor_pos));
+        // edits may have made the top line shorter
+        let top_rows = self.row_starts(self.offset.row).len();
+        let top = (self.offset.row, self.wrap_offset.min(top_rows - 1));
+        let lowest_top = self.rows_up(cursor, height.max(1) - 1);
+        (self.offset.row, self.wrap_offset) = match cursor < top {
+            true => cursor,
+            false => top.max(lowest_top),
+        };
+    }
+
+    fn scroll_wrapped(&mut self, up: bool) {
+        let top = (self.offset.row, self.wrap_offset);
+        let top = match up {
+            true => self.rows_up(top, SCROLL_ROWS),
+            false => self.rows_down(top, SCROLL_ROWS),
+        };
+        (self.offset.row, self.wrap_offset) = top;
+        let bottom = self.rows_down(top, self.view_height.max(1) - 1);
+        let cursor = (self.cursor_pos.row, self.sub_row(self.cursor_pos));
+        let to = cursor.clamp(top, bottom);
+        if to != cursor {
+            let cell = self.get_cursor_view_pos().col;
+            self.selection_anchor = None;
+            self.cursor_pos = Position {
+                row: to.0,
+                col: self.col_in_screen_row(to.0, to.1, cell),
+            };
+        }
     }
 }
 
 // handle mouse event
 impl Doc {
-    pub fn process_mouse_event(&mut self, mouse_event: &MouseEvent) {
+    /// `cell` is where the event happened, relative to the top left of the text.
+    pub fn process_mouse_event(&mut self, mouse_event: &MouseEvent, cell: Position) {
+        self.history.seal();
         match mouse_event {
             MouseEvent::Press(button_event, _, _) => match button_event {
-                termion::event::MouseButton::Left => {}
+                termion::event::MouseButton::Left => self.click(self.position_at(cell)),
                 termion::event::MouseButton::Right => {}
                 termion::event::MouseButton::Middle => {}
-                termion::event::MouseButton::WheelUp => self.row_up(),
-                termion::event::MouseButton::WheelDown => self.row_down(),
+                termion::event::MouseButton::WheelUp => self.scroll(true),
+                termion::event::MouseButton::WheelDown => self.scroll(false),
             },
-            MouseEvent::Release(_, _) => {}
-            MouseEvent::Hold(_, _) => {}
+            MouseEvent::Release(_, _) => {
+                if self.get_selection().is_none() {
+                    self.selection_anchor = None;
+                }
+            }
+            MouseEvent::Hold(_, _) => {
+                if self.selection_anchor.is_some() {
+                    self.cursor_pos = self.position_at(cell);
+                }
+            }
+        }
+    }
+
+    fn click(&mut self, pos: Position) {
+        let double = matches!(self.last_click, Some((time, last))
+            if last == pos && time.elapsed() < DOUBLE_CLICK);
+        if double {
+            let cols = text::word_range(self.buffer.line(pos.row), pos.col);
+            self.selection_anchor = Some(Position {
+                row: pos.row,
+                col: cols.start,
+            });
+            self.cursor_pos = Position {
+                row: pos.row,
+                col: cols.end,
+            };
+            self.last_click = None;
+        } else {
+            // dragging from here selects
+            self.cursor_pos = pos;
+            self.selection_anchor = Some(pos);
+            self.last_click = Some((Instant::now(), pos));
+        }
+    }
+
+    // moves the view, the cursor only moves to stay on screen
+    fn scroll(&mut self, up: bool) {
+        if self.wrap_width.is_some() {
+            return self.scroll_wrapped(up);
+        }
+        let last_line = self.line_count() - 1;
+        self.offset.row = match up {
+            true => self.offset.row.saturating_sub(SCROLL_ROWS),
+            false => (self.offset.row + SCROLL_ROWS).min(last_line),
+        };
+        let last_row = (self.offset.row + self.view_height.max(1) - 1).min(last_line);
+        let row = self.cursor_pos.row.clamp(self.offset.row, last_row);
+        if row != self.cursor_pos.row {
+            self.selection_anchor = None;
+            self.move_to_row(row);
+        }
+    }
+
+    // text position shown at a screen cell of the text area
+    fn position_at(&self, cell: Position) -> Position {
+        if self.wrap_width.is_some() {
+            let (row, sub) = self.rows_down((self.offset.row, self.wrap_offset), cell.row);
+            return Position {
+                row,
+                col: self.col_in_screen_row(row, sub, cell.col),
+            };
+        }
+        let row = (self.offset.row + cell.row).min(self.line_count() - 1);
+        Position {
+            row,
+            col: text::col_at_cell(
+                self.buffer.line(row),
+                self.offset.col + cell.col,
+                self.tab_width,
+            ),
         }
     }
 }
@@ -325,14 +1111,996 @@ impl Doc {
     pub(crate) fn process_command_input(&mut self, input: String) -> Task {
         let task = match self.task_pending {
             TaskPending::SaveFile => {
-                self.file_path = Some(input);
+                self.set_file_path(input);
                 self.task_pending = TaskPending::None;
                 self.process_save_file()
             }
             TaskPending::None => unimplemented!("No task asking for input"),
-            TaskPending::OpenDoc => Task::OpenDoc(input),
+            TaskPending::Search => self.finish_search(&input),
+            TaskPending::ReplacePattern => return self.set_replace_pattern(&input),
+            TaskPending::ReplaceWith => return self.start_replace(input),
+            TaskPending::ReplaceConfirm => return self.confirm_replace(&input),
+            TaskPending::CloseConfirm => {
+                self.task_pending = TaskPending::None;
+                return self.confirm_close(&input);
+            }
+            TaskPending::DiskChanged => {
+                self.task_pending = TaskPending::None;
+                return self.confirm_disk_change(&input);
+            }
+            TaskPending::DiskDeleted => {
+                self.task_pending = TaskPending::None;
+                return self.confirm_disk_deleted(&input);
+            }
+            TaskPending::SwapFound => {
+                self.task_pending = TaskPending::None;
+                return self.confirm_swap(&input);
+            }
+            TaskPending::VimCommand => {
+                self.task_pending = TaskPending::None;
+                return self.run_ex_command(input.trim());
+            }
+            TaskPending::CopyRegister | TaskPending::PasteRegister => {
+                let copy = matches!(self.task_pending, TaskPending::CopyRegister);
+                self.task_pending = TaskPending::None;
+                let register = input.chars().next().unwrap_or(DEFAULT_REGISTER);
+                return match copy {
+                    true => self.copy(register),
+                    false => Task::Paste(register),
+                };
+            }
         };
         self.task_pending = TaskPending::None;
         task
     }
+
+    /// Called on every change of the input while the prompt is open.
+    pub(crate) fn process_command_change(&mut self, input: String) -> Task {
+        if let TaskPending::Search = self.task_pending {
+            self.update_search(&input);
+        }
+        Task::None
+    }
+
+    pub(crate) fn process_command_cancel(&mut self) -> Task {
+        match self.task_pending {
+            TaskPending::Search => {
+                self.search = None;
+                self.cursor_pos = self.search_origin;
+                self.command_status = "Search cancelled".to_string();
+            }
+            TaskPending::ReplaceConfirm => return self.finish_replace(),
+            TaskPending::SaveFile => {
+                self.close_after_save = false;
+                self.command_status = "Cancelled".to_string();
+            }
+            TaskPending::DiskChanged => {
+                self.task_pending = TaskPending::None;
+                return self.confirm_disk_change("i");
+            }
+            TaskPending::DiskDeleted => {
+                self.task_pending = TaskPending::None;
+                return self.confirm_disk_deleted("k");
+            }
+            TaskPending::SwapFound => {
+                self.task_pending = TaskPending::None;
+                return self.confirm_swap("k");
+            }
+            TaskPending::ReplacePattern | TaskPending::ReplaceWith => {
+                self.search = None;
+                self.command_status = "Cancelled".to_string();
+            }
+            _ => self.command_status = "Cancelled".to_string(),
+        }
+        self.task_pending = TaskPending::None;
+        Task::None
+    }
+}
+
+// changes made to the file by other programs
+impl Doc {
+    fn changed_on_disk(&mut self) -> Option<DiskChange> {
+        match (&self.file_path, &mut self.disk_state) {
+            (Some(path), Some(disk_state)) => disk_state.changed(path),
+            _ => None,
+        }
+    }
+
+    /// Asks what to do when the file changed on disk or was deleted, unless
+    /// a prompt is open.
+    pub(crate) fn check_disk(&mut self) -> Task {
+        if !matches!(self.task_pending, TaskPending::None) {
+            return Task::None;
+        }
+        match self.changed_on_disk() {
+            Some(change) => self.ask_disk_change(change),
+            None => Task::None,
+        }
+    }
+
+    fn ask_disk_change(&mut self, change: DiskChange) -> Task {
+        let title = self.get_title();
+        let question = match change {
+            DiskChange::Modified => {
+                self.task_pending = TaskPending::DiskChanged;
+                format!(
+                    "{} changed on disk. (r)eload (o)verwrite (d)iff (i)gnore",
+                    title
+                )
+            }
+            DiskChange::Deleted => {
+                self.task_pending = TaskPending::DiskDeleted;
+                format!("{} deleted on disk. (k)eep (w)rite back", title)
+            }
+        };
+        Task::AskChoice(question)
+    }
+
+    fn confirm_disk_deleted(&mut self, choice: &str) -> Task {
+        match choice {
+            "w" => self.write_file(),
+            _ => {
+                // no longer watched, and unsaved until written again
+                self.disk_state = None;
+                self.saved_state = u64::MAX;
+                self.command_status = format!("Kept {}, deleted on disk", self.get_title());
+                Task::None
+            }
+        }
+    }
+
+    fn confirm_disk_change(&mut self, choice: &str) -> Task {
+        match choice {
+            "r" => {
+                self.close_after_save = false;
+                self.reload()
+            }
+            "o" => self.write_file(),
+            "d" => {
+                self.close_after_save = false;
+                self.show_disk_diff()
+            }
+            _ => {
+                // keep our version, the next save overwrites the file
+                self.close_after_save = false;
+                if let Some(path) = &self.file_path {
+                    self.disk_state = DiskState::read(path);
+                }
+                self.command_status = format!("Kept the edited {}", self.get_title());
+                Task::None
+            }
+        }
+    }
+
+    fn read_disk(&self) -> Option<String> {
+        fs::read_to_string(self.file_path.as_ref()?).ok()
+    }
+
+    fn reload(&mut self) -> Task {
+        let Some(content) = self.read_disk() else {
+            self.command_status = format!("Unable to read {}", self.get_title());
+            return Task::None;
+        };
+        self.replace_content(&content);
+        self.saved_format = self.format;
+        self.saved_state = self.history.get_state_id();
+        self.disk_state = self
+            .file_path
+            .as_deref()
+            .and_then(|path| DiskState::of(path, content.as_bytes()));
+        self.command_status = format!("Reloaded {}", self.get_title());
+        Task::None
+    }
+
+    // replaces the content as one undoable edit
+    fn replace_content(&mut self, content: &str) {
+        let (format, lines) = FileFormat::detect(content);
+        let last = self.line_count() - 1;
+        let end = Position {
+            row: last,
+            col: self.line_cols(last),
+        };
+
+        self.history.seal();
+        self.history.begin_group();
+        self.delete_text(Position { row: 0, col: 0 }, end, EditKind::Other);
+        self.insert_text(
+            Position { row: 0, col: 0 },
+            &lines.join("\n"),
+            EditKind::Other,
+        );
+        self.history.end_group();
+
+        self.format = format;
+        self.cursor_pos.row = self.cursor_pos.row.min(self.line_count() - 1);
+        self.cursor_pos.col = self.cursor_pos.col.min(self.line_cols(self.cursor_pos.row));
+    }
+
+    fn show_disk_diff(&mut self) -> Task {
+        let Some(content) = self.read_disk() else {
+            self.command_status = format!("Unable to read {}", self.get_title());
+            return Task::None;
+        };
+        let title = self.get_title();
+        let (_, disk_lines) = FileFormat::detect(&content);
+        let lines: Vec<&str> = self.buffer.lines().collect();
+        let diff = diff::unified(
+            &disk_lines,
+            &lines,
+            &format!("{} (disk)", title),
+            &format!("{} (edited)", title),
+        );
+        Task::ShowDiff(format!("{}.diff", title), diff)
+    }
+}
+
+// swap files keeping unsaved changes through a crash
+impl Doc {
+    /// An untitled doc holding the content of `leftover`, unsaved. The
+    /// swap file is the doc's own from now on.
+    pub(crate) fn recovered(leftover: Leftover) -> Self {
+        let mut doc = Self::new();
+        doc.replace_content(&leftover.content);
+        doc.cursor_pos = Position { row: 0, col: 0 };
+        doc.swap_file = Some(leftover.file);
+        doc.command_status = "Recovered an untitled doc".to_string();
+        doc
+    }
+
+    /// Writes the swap file in `dir` if the content changed since the last
+    /// write, removes it once nothing is unsaved.
+    pub(crate) fn write_swap(&mut self, dir: &Path) {
+        // generated text like a diff can be made again
+        if self.title.is_some() {
+            return;
+        }
+        if !self.is_dirty() {
+            self.remove_swap();
+            return;
+        }
+        let file = match (&self.file_path, &self.swap_file) {
+            (Some(path), Some(file)) if swap::is_file_for(dir, file, path) => Some(file.clone()),
+            (Some(path), _) => swap::file_for(dir, path),
+            (None, file) => Some(file.clone().unwrap_or_else(|| swap::untitled_file(dir))),
+        };
+        // other editors own every name, their swap files are left alone
+        let Some(file) = file else {
+            self.remove_swap();
+            return;
+        };
+        let state = (self.history.get_state_id(), self.format);
+        if self.swap_file.as_ref() != Some(&file) {
+            // the doc was given a path, or is written for the first time
+            self.remove_swap();
+        } else if self.swapped == Some(state) {
+            return;
+        }
+        let content = self.get_doc_content();
+        if swap::write(&file, self.file_path.as_deref(), &content).is_ok() {
+            self.swap_file = Some(file);
+            self.swapped = Some(state);
+        }
+    }
+
+    pub(crate) fn remove_swap(&mut self) {
+        if let Some(file) = self.swap_file.take() {
+            swap::remove(&file);
+        }
+        self.swapped = None;
+    }
+
+    /// Asks what to do with the swap file in `dir` an editor that died left
+    /// for the file, if there is one, or warns when another editor has it
+    /// open.
+    pub(crate) fn check_swap(&mut self, dir: &Path) -> Task {
+        let Some(path) = self.file_path.as_deref() else {
+            return Task::None;
+        };
+        self.swap_found = swap::leftover_for(dir, path);
+        if self.swap_found.is_none() {
+            if let Some(pid) = swap::running_owner(dir, path) {
+                self.command_status = format!(
+                    "{} is open in another editor (pid {})",
+                    self.get_title(),
+                    pid
+                );
+            }
+            return Task::None;
+        }
+        self.task_pending = TaskPending::SwapFound;
+        Task::AskChoice(format!(
+            "Swap file found for {}. (r)ecover (d)iscard (k)eep",
+            self.get_title()
+        ))
+    }
+
+    fn confirm_swap(&mut self, choice: &str) -> Task {
+        let Some(leftover) = self.swap_found.take() else {
+            return Task::None;
+        };
+        match choice {
+            "r" => {
+                self.replace_content(&leftover.content);
+                // taken over, the next write states this editor as owner
+                self.remove_swap();
+                self.swap_file = Some(leftover.file);
+                self.command_status = format!("Recovered {}", self.get_title());
+            }
+            "d" => {
+                swap::remove(&leftover.file);
+                self.command_status = format!("Discarded the changes to {}", self.get_title());
+            }
+            // asked again on the next open, edits go to another swap file
+            _ => self.command_status = format!("Kept the swap file of {}", self.get_title()),
+        }
+        Task::None
+    }
+}
+
+// selection and clipboard
+impl Doc {
+    /// Start and end of the selected text, in order.
+    pub(crate) fn get_selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.selection_anchor?;
+        let (start, end) = (anchor.min(self.cursor_pos), anchor.max(self.cursor_pos));
+        match self.vim.mode {
+            // vim selects the character under the cursor too
+            Mode::Visual => Some((start, self.next_in_line(end))),
+            Mode::VisualLine => Some((
+                Position {
+                    row: start.row,
+                    col: 0,
+                },
+                Position {
+                    row: end.row,
+                    col: self.line_cols(end.row),
+                },
+            )),
+            Mode::Normal | Mode::Insert => (start != end).then_some((start, end)),
+        }
+    }
+
+    fn next_in_line(&self, pos: Position) -> Position {
+        Position {
+            row: pos.row,
+            col: (pos.col + 1).min(self.line_cols(pos.row)),
+        }
+    }
+
+    fn get_text(&self, start: Position, end: Position) -> String {
+        let (start, end) = (self.byte_pos(start), self.byte_pos(end));
+        if start.row == end.row {
+            return self.buffer.line(start.row)[start.col..end.col].to_string();
+        }
+        let mut text = self.buffer.line(start.row)[start.col..].to_string();
+        for row in start.row + 1..end.row {
+            text.push('\n');
+            text.push_str(self.buffer.line(row));
+        }
+        text.push('\n');
+        text.push_str(&self.buffer.line(end.row)[..end.col]);
+        text
+    }
+
+    fn delete_selection(&mut self) -> Option<String> {
+        let (start, end) = self.get_selection()?;
+        Some(self.delete_text(start, end, EditKind::Other))
+    }
+
+    // the selection, if any, and the new text go away in one undo step
+    fn replace_selection(&mut self, text: &str) {
+        self.history.seal();
+        self.history.begin_group();
+        self.delete_selection();
+        self.insert_text(self.cursor_pos, text, EditKind::Other);
+        self.history.end_group();
+    }
+
+    fn copy(&mut self, register: char) -> Task {
+        match self.get_selection() {
+            Some((start, end)) => Task::Copy(register, self.get_text(start, end)),
+            None => {
+                self.command_status = "Nothing selected".to_string();
+                Task::None
+            }
+        }
+    }
+
+    fn cut(&mut self) -> Task {
+        self.history.seal();
+        match self.delete_selection() {
+            Some(text) => Task::Copy(DEFAULT_REGISTER, text),
+            None => {
+                self.command_status = "Nothing selected".to_string();
+                Task::None
+            }
+        }
+    }
+
+    pub(crate) fn paste(&mut self, text: &str) {
+        match self.vim_paste.take() {
+            Some((before, count)) => self.vim_paste_text(text, before, count),
+            None => self.replace_selection(text),
+        }
+        self.history.seal();
+    }
+}
+
+// vim mode
+impl Doc {
+    pub fn get_mode_name(&self) -> &'static str {
+        self.vim.mode.name()
+    }
+
+    fn run_vim_command(&mut self, command: Command) -> Task {
+        let task = match command {
+            Command::Pending => Task::None,
+            Command::Move(motion, count) => {
+                self.cursor_pos = self.motion_target(motion, count);
+                Task::None
+            }
+            Command::Operate {
+                operator,
+                target,
+                count,
+                register,
+            } => self.vim_operate(
+                operator,
+                target,
+                count,
+                register.unwrap_or(DEFAULT_REGISTER),
+            ),
+            Command::OperateSelection(operator, register) => match self.get_selection() {
+                Some((start, end)) => {
+                    let linewise = self.vim.mode == Mode::VisualLine;
+                    self.vim.mode = Mode::Normal;
+                    self.selection_anchor = None;
+                    let register = register.unwrap_or(DEFAULT_REGISTER);
+                    self.apply_operator(operator, start, end, linewise, register)
+                }
+                None => Task::None,
+            },
+            Command::SelectObject(object, inner) => {
+                if let Some((start, end)) =
+                    vim::object_range(&self.buffer, self.cursor_pos, object, inner)
+                {
+                    self.selection_anchor = Some(start);
+                    self.cursor_pos = self.prev_pos(end);
+                }
+                Task::None
+            }
+            Command::Insert(at) => {
+                self.vim_insert(at);
+                Task::None
+            }
+            Command::Paste {
+                before,
+                count,
+                register,
+            } => {
+                // in visual mode the pasted text replaces the selection
+                let before = match self.get_selection() {
+                    Some((start, end)) if self.vim.mode != Mode::Normal => {
+                        self.delete_text(start, end, EditKind::Other);
+                        self.vim.mode = Mode::Normal;
+                        // at the line end the cursor steps back onto the last character
+                        start.col == 0 || start.col < self.line_cols(start.row)
+                    }
+                    _ => before,
+                };
+                self.vim_paste = Some((before, count.unwrap_or(1)));
+                Task::Paste(register.unwrap_or(DEFAULT_REGISTER))
+            }
+            Command::Undo(count) => {
+                for _ in 0..count.unwrap_or(1) {
+                    let state = self.history.get_state_id();
+                    self.undo();
+                    // nothing left to undo
+                    if self.history.get_state_id() == state {
+                        break;
+                    }
+                }
+                Task::None
+            }
+            Command::Redo(count) => {
+                for _ in 0..count.unwrap_or(1) {
+                    let state = self.history.get_state_id();
+                    self.redo();
+                    if self.history.get_state_id() == state {
+                        break;
+                    }
+                }
+                Task::None
+            }
+            Command::Visual(mode) => {
+                if self.vim.mode == mode {
+                    self.vim.mode = Mode::Normal;
+                    self.selection_anchor = None;
+                } else {
+                    if self.vim.mode == Mode::Normal {
+                        self.selection_anchor = Some(self.cursor_pos);
+                    }
+                    self.vim.mode = mode;
+                }
+                Task::None
+            }
+            Command::NormalMode => {
+                match self.vim.mode {
+                    Mode::Insert => self.cursor_pos.col = self.cursor_pos.col.saturating_sub(1),
+                    Mode::Visual | Mode::VisualLine => self.selection_anchor = None,
+                    // Esc keeps clearing the search highlighting
+                    Mode::Normal => self.search = None,
+                }
+                self.vim.mode = Mode::Normal;
+                Task::None
+            }
+            Command::Ex => {
+                self.task_pending = TaskPending::VimCommand;
+                Task::AskInput(String::new())
+            }
+            Command::Search => self.start_search(),
+            Command::SearchNext(forward) => self.search_next(forward),
+        };
+        // outside insert mode the cursor stays on a character
+        if self.vim.mode != Mode::Insert {
+            let cols = self.line_cols(self.cursor_pos.row);
+            self.cursor_pos.col = self.cursor_pos.col.min(cols.saturating_sub(1));
+        }
+        task
+    }
+
+    fn motion_target(&self, motion: Motion, count: Option<usize>) -> Position {
+        let pos = self.cursor_pos;
+        let n = count.unwrap_or(1);
+        let last_line = self.line_count() - 1;
+        let on_row = |row: usize| Position {
+            row,
+            col: text::col_at_cell(
+                self.buffer.line(row),
+                self.get_cursor_cell(),
+                self.tab_width,
+            ),
+        };
+        let first_non_blank = |row: usize| Position {
+            row,
+            col: vim::first_non_blank(self.buffer.line(row)),
+        };
+        let repeat = |step: fn(&Buffer, Position) -> Position| {
+            let mut pos = pos;
+            for _ in 0..n {
+                let next = step(&self.buffer, pos);
+                // stuck at the start or end of the buffer
+                if next == pos {
+                    break;
+                }
+                pos = next;
+            }
+            pos
+        };
+        match motion {
+            Motion::Left => Position {
+                row: pos.row,
+                col: pos.col.saturating_sub(n),
+            },
+            Motion::Right => Position {
+                row: pos.row,
+                col: pos.col.saturating_add(n).min(self.line_cols(pos.row)),
+            },
+            Motion::Up => on_row(pos.row.saturating_sub(n)),
+            Motion::Down => on_row(pos.row.saturating_add(n).min(last_line)),
+            Motion::WordStart => repeat(vim::word_start),
+            Motion::WordBack => repeat(vim::word_back),
+            Motion::WordEnd => repeat(vim::word_end),
+            Motion::LineStart => Position {
+                row: pos.row,
+                col: 0,
+            },
+            Motion::LineEnd => {
+                let row = pos.row.saturating_add(n - 1).min(last_line);
+                Position {
+                    row,
+                    col: self.line_cols(row),
+                }
+            }
+            Motion::FirstLine => first_non_blank(count.map_or(0, |n| n - 1).min(last_line)),
+            Motion::LastLine => first_non_blank(count.map_or(last_line, |n| n - 1).min(last_line)),
+        }
+    }
+
+    fn vim_operate(
+        &mut self,
+        operator: Operator,
+        target: Target,
+        count: Option<usize>,
+        register: char,
+    ) -> Task {
+        let pos = self.cursor_pos;
+        match target {
+            Target::Line => {
+                let last = pos
+                    .row
+                    .saturating_add(count.unwrap_or(1) - 1)
+                    .min(self.line_count() - 1);
+                let end = Position { row: last, col: 0 };
+                self.apply_operator(operator, pos, end, true, register)
+            }
+            Target::Object { object, inner } => {
+                match vim::object_range(&self.buffer, pos, object, inner) {
+                    Some((start, end)) => {
+                        self.apply_operator(operator, start, end, false, register)
+                    }
+                    None => Task::None,
+                }
+            }
+            Target::Motion(motion) => {
+                // `cw` on a word changes up to its end, like `ce`
+                let change_word = match (operator, motion, count) {
+                    (Operator::Change, Motion::WordStart, None) => {
+                        vim::change_word_end(&self.buffer, pos)
+                    }
+                    _ => None,
+                };
+                let (motion, mut target) = match change_word {
+                    Some(end) => (Motion::WordEnd, end),
+                    None => (motion, self.motion_target(motion, count)),
+                };
+                if motion.is_linewise() {
+                    let (start, end) = (pos.min(target), pos.max(target));
+                    return self.apply_operator(operator, start, end, true, register);
+                }
+                // `dw` on the last word of a line leaves the line break alone
+                if motion == Motion::WordStart && target.row > pos.row && target.col == 0 {
+                    let row = target.row - 1;
+                    target = Position {
+                        row,
+                        col: self.line_cols(row),
+                    };
+                }
+                let (start, mut end) = (pos.min(target), pos.max(target));
+                if motion.is_inclusive() {
+                    end = self.next_in_line(end);
+                }
+                self.apply_operator(operator, start, end, false, register)
+            }
+        }
+    }
+
+    // linewise operators work on the rows from start to end
+    fn apply_operator(
+        &mut self,
+        operator: Operator,
+        start: Position,
+        end: Position,
+        linewise: bool,
+        register: char,
+    ) -> Task {
+        if !linewise {
+            if start == end {
+                return Task::None;
+            }
+            let text = self.get_text(start, end);
+            match operator {
+                Operator::Yank => self.cursor_pos = start,
+                Operator::Delete => {
+                    self.delete_text(start, end, EditKind::Other);
+                }
+                Operator::Change => {
+                    self.delete_text(start, end, EditKind::Other);
+                    self.vim.mode = Mode::Insert;
+                }
+            }
+            return Task::Copy(register, text);
+        }
+
+        let (first, last) = (start.row, end.row);
+        let last_col = self.line_cols(last);
+        let mut text = self.get_text(
+            Position { row: first, col: 0 },
+            Position {
+                row: last,
+                col: last_col,
+            },
+        );
+        // a trailing line break marks the text as whole lines for `p`
+        text.push('\n');
+        match operator {
+            Operator::Yank => self.cursor_pos.row = first,
+            Operator::Delete => {
+                let (start, end) = if last + 1 < self.line_count() {
+                    (
+                        Position { row: first, col: 0 },
+                        Position {
+                            row: last + 1,
+                            col: 0,
+                        },
+                    )
+                } else if first > 0 {
+                    (
+                        Position {
+                            row: first - 1,
+                            col: self.line_cols(first - 1),
+                        },
+                        Position {
+                            row: last,
+                            col: last_col,
+                        },
+                    )
+                } else {
+                    (
+                        Position { row: 0, col: 0 },
+                        Position {
+                            row: last,
+                            col: last_col,
+                        },
+                    )
+                };
+                self.delete_text(start, end, EditKind::Other);
+                let row = first.min(self.line_count() - 1);
+                self.cursor_pos = Position {
+                    row,
+                    col: vim::first_non_blank(self.buffer.line(row)),
+                };
+            }
+            Operator::Change => {
+                let end = Position {
+                    row: last,
+                    col: last_col,
+                };
+                self.delete_text(Position { row: first, col: 0 }, end, EditKind::Other);
+                self.vim.mode = Mode::Insert;
+            }
+        }
+        Task::Copy(register, text)
+    }
+
+    fn vim_insert(&mut self, at: InsertAt) {
+        let row = self.cursor_pos.row;
+        match at {
+            InsertAt::Cursor => {}
+            InsertAt::After => self.cursor_pos = self.next_in_line(self.cursor_pos),
+            InsertAt::LineStart => {
+                self.cursor_pos.col = vim::first_non_blank(self.buffer.line(row))
+            }
+            InsertAt::LineEnd => self.cursor_pos.col = self.line_cols(row),
+            InsertAt::LineBelow => {
+                let end = Position {
+                    row,
+                    col: self.line_cols(row),
+                };
+                self.insert_text(end, "\n", EditKind::Other);
+            }
+            InsertAt::LineAbove => {
+                self.insert_text(Position { row, col: 0 }, "\n", EditKind::Other);
+                self.cursor_pos = Position { row, col: 0 };
+            }
+        }
+        self.vim.mode = Mode::Insert;
+    }
+
+    fn vim_paste_text(&mut self, text: &str, before: bool, count: usize) {
+        let count = count.min(MAX_PASTE_BYTES / text.len().max(1)).max(1);
+        let text = text.repeat(count);
+        let pos = self.cursor_pos;
+        self.history.seal();
+        self.history.begin_group();
+        if let Some(lines) = text.strip_suffix('\n') {
+            let row = if before { pos.row } else { pos.row + 1 };
+            if row < self.line_count() {
+                self.insert_text(Position { row, col: 0 }, &text, EditKind::Other);
+            } else {
+                let last = self.line_count() - 1;
+                let end = Position {
+                    row: last,
+                    col: self.line_cols(last),
+                };
+                self.insert_text(end, &format!("\n{}", lines), EditKind::Other);
+            }
+            self.cursor_pos = Position {
+                row,
+                col: vim::first_non_blank(self.buffer.line(row)),
+            };
+        } else {
+            let at = if before { pos } else { self.next_in_line(pos) };
+            self.insert_text(at, &text, EditKind::Other);
+            // on the last pasted character
+            self.cursor_pos.col = self.cursor_pos.col.saturating_sub(1);
+        }
+        self.history.end_group();
+    }
+
+    // commands typed after `:`
+    fn run_ex_command(&mut self, command: &str) -> Task {
+        match command {
+            "w" => self.process_save_file(),
+            "q" => self.request_close(),
+            "q!" => Task::CloseCurrentTab,
+            "wq" | "x" => {
+                self.close_after_save = true;
+                self.process_save_file()
+            }
+            "qa" => Task::Quit,
+            _ => {
+                if let Some(path) = command.strip_prefix("e ") {
+                    return Task::OpenDoc(path.trim().to_string());
+                }
+                if let Some(path) = command.strip_prefix("w ") {
+                    self.set_file_path(path.trim().to_string());
+                    return self.process_save_file();
+                }
+                match command.parse::<usize>() {
+                    Ok(line) => {
+                        self.cursor_pos = Position {
+                            row: line.saturating_sub(1).min(self.line_count() - 1),
+                            col: 0,
+                        };
+                    }
+                    Err(_) => self.command_status = format!("Not an editor command: {}", command),
+                }
+                Task::None
+            }
+        }
+    }
+}
+
+// search and replace
+impl Doc {
+    fn start_search(&mut self) -> Task {
+        self.search_origin = self.cursor_pos;
+        self.task_pending = TaskPending::Search;
+        Task::AskInput("Search".to_string())
+    }
+
+    fn update_search(&mut self, input: &str) {
+        self.search = Search::new(input);
+        let origin = self.byte_pos(self.search_origin);
+        let found = self
+            .search
+            .as_ref()
+            .and_then(|search| search.find_forward(&self.buffer, origin));
+        match found {
+            Some(found) => self.cursor_pos = self.grapheme_pos(found.start()),
+            None => self.cursor_pos = self.search_origin,
+        }
+    }
+
+    fn finish_search(&mut self, input: &str) -> Task {
+        self.update_search(input);
+        let found = self.search.as_ref().and_then(|search| {
+            search.find_forward(&self.buffer, self.byte_pos(self.search_origin))
+        });
+        self.command_status = match found {
+            Some(_) => format!("Search: {} (Ctrl+G next, Alt+G previous)", input),
+            None => format!("No match for: {}", input),
+        };
+        Task::None
+    }
+
+    fn search_next(&mut self, forward: bool) -> Task {
+        let Some(search) = &self.search else {
+            self.command_status = "No active search (Ctrl+F to search)".to_string();
+            return Task::None;
+        };
+        let found = if forward {
+            let mut from = self.next_pos(self.cursor_pos);
+            if from.row == self.cursor_pos.row && from.col == self.cursor_pos.col {
+                from = Position { row: 0, col: 0 };
+            }
+            search.find_forward(&self.buffer, self.byte_pos(from))
+        } else {
+            search.find_backward(&self.buffer, self.byte_pos(self.cursor_pos))
+        };
+        match found {
+            Some(found) => self.cursor_pos = self.grapheme_pos(found.start()),
+            None => self.command_status = "No match".to_string(),
+        }
+        Task::None
+    }
+
+    fn set_replace_pattern(&mut self, pattern: &str) -> Task {
+        match Search::regex(pattern) {
+            Ok(search) => {
+                self.search = Some(search);
+                self.task_pending = TaskPending::ReplaceWith;
+                Task::AskInput("Replace with ($1 for groups)".to_string())
+            }
+            Err(err) => {
+                self.task_pending = TaskPending::None;
+                self.command_status = format!("Invalid regex: {}", err);
+                Task::None
+            }
+        }
+    }
+
+    fn start_replace(&mut self, replacement: String) -> Task {
+        let start = self.byte_pos(self.cursor_pos);
+        self.replace = Some(Replace::new(replacement, start));
+        self.offer_replace(start)
+    }
+
+    // moves to the next match from byte position `from` and asks what to do with it
+    fn offer_replace(&mut self, from: Position) -> Task {
+        match self.next_replace_match(from) {
+            Some(found) => {
+                self.cursor_pos = self.grapheme_pos(found.start());
+                self.task_pending = TaskPending::ReplaceConfirm;
+                Task::AskChoice("Replace? (y)es (n)o (a)ll (q)uit".to_string())
+            }
+            None => self.finish_replace(),
+        }
+    }
+
+    fn next_replace_match(&mut self, from: Position) -> Option<Match> {
+        let (Some(search), Some(replace)) = (&self.search, &mut self.replace) else {
+            return None;
+        };
+        replace.next_match(search, &self.buffer, from)
+    }
+
+    fn confirm_replace(&mut self, choice: &str) -> Task {
+        let cursor = self.byte_pos(self.cursor_pos);
+        let Some(found) = self
+            .search
+            .as_ref()
+            .and_then(|search| search.find_forward(&self.buffer, cursor))
+        else {
+            return self.finish_replace();
+        };
+        match choice {
+            "y" => {
+                let end = self.replace_match(&found);
+                self.offer_replace(end)
+            }
+            "n" => {
+                let next = Position {
+                    row: found.row,
+                    col: found.range.end,
+                };
+                self.offer_replace(next)
+            }
+            "a" => {
+                self.history.begin_group();
+                let mut found = Some(found);
+                while let Some(current) = found {
+                    let end = self.replace_match(&current);
+                    found = self.next_replace_match(end);
+                }
+                self.history.end_group();
+                self.finish_replace()
+            }
+            _ => self.finish_replace(),
+        }
+    }
+
+    // replaces one match as a single undo step, returns the byte position after it
+    fn replace_match(&mut self, found: &Match) -> Position {
+        let (Some(search), Some(replace)) = (&self.search, &mut self.replace) else {
+            return found.start();
+        };
+        let expanded = replace.expand(search, &self.buffer, found);
+        replace.record_replacement(found, expanded.len());
+
+        let start = self.grapheme_pos(found.start());
+        let end = self.grapheme_pos(Position {
+            row: found.row,
+            col: found.range.end,
+        });
+        let grouped = self.history.is_grouping();
+        if !grouped {
+            self.history.begin_group();
+        }
+        self.delete_text(start, end, EditKind::Other);
+        self.insert_text(start, &expanded, EditKind::Other);
+        if !grouped {
+            self.history.end_group();
+        }
+        self.byte_pos(self.cursor_pos)
+    }
+
+    fn finish_replace(&mut self) -> Task {
+        let count = self.replace.take().map_or(0, |replace| replace.count);
+        self.search = None;
+        self.task_pending = TaskPending::None;
+        self.command_status = format!("Replaced {} occurrence(s)", count);
+        Task::None
+    }
 }
@@ -1,97 +1,177 @@
 use std::cmp::max;
-use std::io::{self, stdout};
+use std::collections::HashMap;
+use std::env;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use termion::event::Event;
-use termion::input::MouseTerminal;
-use termion::raw::IntoRawMode;
+use termion::event::{Event, Key};
 use termion::style;
-use termion::{event::Key, input::TermRead};
 
+use crate::clipboard::{self, Clipboard, DEFAULT_REGISTER};
 use crate::common::{Position, Size, Task};
-use crate::doc::Doc;
+use crate::doc::{Doc, DocView};
+use crate::event_loop::{EditorEvent, EventLoop, Timer, WorkerMessage};
 use crate::filetree::FileTree;
+use crate::finder::{self, Finder};
+use crate::keymap::{self, Action, Context, Lookup};
+use crate::layout::{Axis, Layout, PaneId, Rect};
+use crate::palette::Palette;
+use crate::recent::RecentFiles;
 use crate::status_line::{InputStatus, StatusLine};
-use crate::{config::Config, terminal::Terminal};
+use crate::swap;
+use crate::{config::Config, terminal::Terminal, text};
 
-#[allow(dead_code)]
-enum FocusComponent {
-    Doc,
-    FileTree,
-}
+// a pane narrower or lower than this is not split further
+const MIN_PANE_WIDTH: usize = 20;
+const MIN_PANE_HEIGHT: usize = 3;
+const DISK_CHECK_PERIOD: Duration = Duration::from_secs(1);
+// a status message is cleared after that long on screen
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
 
-#[allow(dead_code)]
-enum View {
-    Doc,
+enum Pane {
     FileTree,
-    Both(FocusComponent),
+    // the doc shown, and its view while the pane is not the doc pane
+    Doc(usize, DocView),
 }
 
 pub struct Editor {
     active_doc: usize,
+    clipboard: Clipboard,
     config: Config,
-    #[allow(dead_code)]
     file_tree: FileTree,
     cursor_pos: Position,
     docs: Vec<Doc>,
     terminal: Terminal,
+    events: EventLoop,
     status_input_active: bool,
     status_line: StatusLine,
-    view: View,
+    // status message the clear timer was set for
+    shown_status: String,
+    layout: Layout,
+    panes: HashMap<PaneId, Pane>,
+    next_pane: PaneId,
+    // the pane keys go to
+    focused_pane: PaneId,
+    // the pane showing active_doc, the focused one unless the file tree is
+    doc_pane: PaneId,
+    // the open prompt asks what to do with unsaved docs before quitting
+    quit_pending: bool,
+    // the open prompt asks whether to keep the untitled docs recovered at
+    // startup, that many docs at the end
+    recovered_pending: usize,
+    quit: bool,
+    // keys typed so far of a bound multi-key sequence
+    pending_keys: Vec<Key>,
+    // open while the status line asks for a command
+    palette: Option<Palette>,
+    // open while the status line asks for a file
+    finder: Option<Finder>,
+    recent: RecentFiles,
+    // where swap files are looked for, none without a state dir
+    swap_dir: Option<PathBuf>,
 }
 
 impl Editor {
-    pub(crate) fn new(config: Config, file_path: Option<String>) -> Self {
-        let mut docs = vec![];
+    pub(crate) fn new(config: Config, file_path: Option<String>, mut terminal: Terminal) -> Self {
+        let swap_dir = config.state_dir.as_deref().map(swap::dir);
+        // untitled docs of an editor that died come back as tabs of their
+        // own, kept or discarded once the user is asked
+        let mut docs: Vec<Doc> = swap_dir
+            .as_deref()
+            .map(swap::leftover_untitled)
+            .unwrap_or_default()
+            .into_iter()
+            .map(Doc::recovered)
+            .collect();
+        let recovered = docs.len();
 
         let doc = if let Some(path) = file_path {
             match Doc::open(&path) {
-                Some(doc) => doc,
+                Some(doc) => Some(doc),
                 None => {
                     let mut doc = Doc::new();
                     doc.set_file_path(path);
                     doc.set_command_status("New named doc created".to_string());
-                    doc
+                    Some(doc)
                 }
             }
+        } else if docs.is_empty() {
+            Some(Doc::new())
         } else {
-            Doc::new()
+            None
         };
-        docs.push(doc);
+        if let Some(doc) = doc {
+            docs.insert(0, doc);
+        }
+        if !config.errors.is_empty() {
+            docs[0].set_command_status(format!("Config error: {}", config.errors.join("; ")));
+        }
+        let view = docs[0].get_view();
 
-        Editor {
+        let mut events = EventLoop::new();
+        terminal.listen(events.sender());
+        events.set_interval(Timer::DiskCheck, DISK_CHECK_PERIOD);
+        if config.general.swap_interval > 0 {
+            let period = Duration::from_secs(config.general.swap_interval as u64);
+            events.set_interval(Timer::Autosave, period);
+        }
+
+        let recent = RecentFiles::load(config.state_dir.as_deref());
+        let mut editor = Editor {
             docs,
             active_doc: 0,
-            terminal: Terminal::new(),
+            clipboard: Clipboard::new(),
+            terminal,
+            events,
             config,
             cursor_pos: Position { row: 1, col: 1 },
             status_input_active: false,
-            view: View::Doc,
-            file_tree: FileTree {},
+            layout: Layout::Pane(0),
+            panes: HashMap::from([(0, Pane::Doc(0, view))]),
+            next_pane: 1,
+            focused_pane: 0,
+            doc_pane: 0,
+            file_tree: FileTree::new(env::current_dir().unwrap_or_else(|_| ".".into())),
             status_line: StatusLine::new(),
+            shown_status: String::new(),
+            quit_pending: false,
+            recovered_pending: 0,
+            quit: false,
+            pending_keys: vec![],
+            palette: None,
+            finder: None,
+            recent,
+            swap_dir,
+        };
+        if recovered > 0 {
+            editor.recovered_pending = recovered;
+            editor.ask_choice(format!(
+                "{} untitled doc(s) recovered. (k)eep (d)iscard",
+                recovered
+            ));
+        } else {
+            let task = editor.check_swap();
+            editor.process_task(task);
         }
+        editor
     }
 
+    /// Handles input, timers and worker messages until the terminal has
+    /// no more input or the editor is quit.
     pub(crate) fn run(&mut self) {
         self.terminal.enter_alternate_screen();
 
-        let stdin = io::stdin();
-        let mut _stdout = MouseTerminal::from(stdout().into_raw_mode().unwrap());
-
         self.update();
-        for c in stdin.events() {
-            let task: Task = match c.unwrap() {
-                Event::Key(Key::Ctrl('q')) => {
-                    break;
-                }
-                Event::Key(key) => self.process_key_event(key),
-                Event::Mouse(mouse_event) => self.process_mouse_event(mouse_event),
-                Event::Unsupported(_) => {
-                    self.terminal.leave_alternate_screen();
-                    todo!("Unsupported event")
-                }
+        loop {
+            let task = match self.events.next() {
+                EditorEvent::InputClosed => break,
+                event => self.handle_event(event),
             };
             self.process_task(task);
-            if self.docs.is_empty() {
+            if self.quit || self.docs.is_empty() {
+                // what was not saved has been discarded
+                self.docs.iter_mut().for_each(Doc::remove_swap);
                 break;
             }
             self.update();
@@ -100,85 +180,304 @@ impl Editor {
         self.terminal.leave_alternate_screen();
     }
 
-    fn process_key_event(&mut self, key: Key) -> Task {
+    fn handle_event(&mut self, event: EditorEvent) -> Task {
+        match event {
+            EditorEvent::Input(Event::Key(key)) => self.process_key_event(key),
+            EditorEvent::Input(Event::Mouse(mouse_event)) => self.process_mouse_event(mouse_event),
+            EditorEvent::Input(Event::Unsupported(bytes)) => {
+                match keymap::parse_shift_arrow(&bytes) {
+                    Some(key) => self.process_shift_key_event(key),
+                    None => Task::None,
+                }
+            }
+            // the update after the event picks up the new size
+            EditorEvent::Resize | EditorEvent::InputClosed => Task::None,
+            EditorEvent::Timer(Timer::DiskCheck) => self.check_disk(),
+            EditorEvent::Timer(Timer::Autosave) => {
+                if let Some(dir) = &self.swap_dir {
+                    self.docs.iter_mut().for_each(|doc| doc.write_swap(dir));
+                }
+                Task::None
+            }
+            EditorEvent::Timer(Timer::ClearStatus) => {
+                let doc = &mut self.docs[self.active_doc];
+                if !self.status_input_active && doc.command_status == self.shown_status {
+                    doc.set_command_status(String::new());
+                }
+                Task::None
+            }
+            EditorEvent::Worker(WorkerMessage::FilesFound(root, files)) => {
+                // the finder may have been closed or opened again meanwhile
+                if let Some(finder) = &mut self.finder {
+                    if finder.get_root() == root {
+                        finder.add_files(files);
+                    }
+                }
+                Task::None
+            }
+        }
+    }
+
+    // only for the doc on screen, and not while a prompt waits
+    fn check_disk(&mut self) -> Task {
         if self.status_input_active {
-            let command = self.status_line.process_key(&key);
-            match command {
-                InputStatus::Processing => Task::None,
-                InputStatus::Cancelled => {
-                    self.status_input_active = false;
-                    Task::SetCommand("Cancelled".to_string())
+            return Task::None;
+        }
+        self.docs[self.active_doc].check_disk()
+    }
+
+    fn process_key_event(&mut self, key: Key) -> Task {
+        let context = self.get_key_context();
+        if context == Context::Doc && self.config.general.vim_mode && self.pending_keys.is_empty() {
+            if let Some(task) = self.docs[self.active_doc].process_vim_key(&key) {
+                return task;
+            }
+        }
+        self.pending_keys.push(key);
+        match self.config.keymap.lookup(context, &self.pending_keys) {
+            // wait for the rest of the sequence
+            Lookup::Prefix => Task::None,
+            // only path prompts complete, others take the keys as typed, a
+            // search for a tab included
+            Lookup::Action(Action::PromptComplete) if !self.status_line.completes_paths() => {
+                let mut task = Task::None;
+                for key in mem::take(&mut self.pending_keys) {
+                    let command = self.status_line.process_key(&key);
+                    task = self.process_input_status(command);
+                    if !self.status_input_active {
+                        break;
+                    }
                 }
-                InputStatus::Done(input) => {
-                    self.status_input_active = false;
-                    self.process_command_event(input)
+                task
+            }
+            Lookup::Action(action) => {
+                self.pending_keys.clear();
+                self.process_action(context, action)
+            }
+            Lookup::None => {
+                let keys = mem::take(&mut self.pending_keys);
+                if keys.len() > 1 {
+                    self.docs[self.active_doc]
+                        .set_command_status(format!("{} is not bound", keymap::format_keys(&keys)));
+                    return Task::None;
+                }
+                match context {
+                    Context::Prompt => {
+                        let command = self.status_line.process_key(&key);
+                        self.process_input_status(command)
+                    }
+                    Context::Doc | Context::Global => self.docs[self.active_doc].process_key(&key),
+                    Context::FileTree => Task::None,
                 }
             }
-        } else {
-            match self.view {
-                View::Doc | View::Both(FocusComponent::Doc) => {
-                    self.docs[self.active_doc].process_key(&key)
+        }
+    }
+
+    // the focused component, which picks the bindings used for keys
+    fn get_key_context(&self) -> Context {
+        if self.status_input_active {
+            return Context::Prompt;
+        }
+        match self.is_tree_focused() {
+            true => Context::FileTree,
+            false => Context::Doc,
+        }
+    }
+
+    fn process_action(&mut self, context: Context, action: Action) -> Task {
+        // Up and Down pick from the open list instead of the prompt history
+        if let (Context::Prompt, Action::PromptUp | Action::PromptDown) = (context, action) {
+            let up = action == Action::PromptUp;
+            if let Some(palette) = &mut self.palette {
+                match up {
+                    true => palette.select_prev(),
+                    false => palette.select_next(),
                 }
-                View::FileTree => todo!(),
-                View::Both(_) => todo!(),
+                return Task::None;
+            }
+            if let Some(finder) = &mut self.finder {
+                match up {
+                    true => finder.select_prev(),
+                    false => finder.select_next(),
+                }
+                return Task::None;
+            }
+        }
+        match (context, action.context()) {
+            (Context::Prompt, _) => {
+                let command = self.status_line.process_action(action);
+                self.process_input_status(command)
+            }
+            (_, Context::FileTree) => self.file_tree.process_action(action),
+            _ => self.docs[self.active_doc].process_action(action),
+        }
+    }
+
+    fn process_input_status(&mut self, command: InputStatus) -> Task {
+        if matches!(command, InputStatus::Cancelled | InputStatus::Done(_)) {
+            // the prompt is answered, the doc status shows again
+            self.status_line.set_status(String::new());
+        }
+        match command {
+            InputStatus::Processing => Task::None,
+            InputStatus::Changed(input) => self.process_command_change(input),
+            InputStatus::Cancelled => {
+                self.status_input_active = false;
+                self.process_command_cancel()
+            }
+            InputStatus::Done(input) => {
+                self.status_input_active = false;
+                self.process_command_event(input)
             }
         }
     }
 
+    fn process_shift_key_event(&mut self, key: Key) -> Task {
+        match self.status_input_active || self.is_tree_focused() {
+            true => Task::None,
+            false => self.docs[self.active_doc].process_shift_key(&key),
+        }
+    }
+
     fn process_mouse_event(&mut self, mouse_event: termion::event::MouseEvent) -> Task {
-        match self.view {
-            View::Doc | View::Both(FocusComponent::Doc) => {
-                self.docs[self.active_doc].process_mouse_event(&mouse_event);
+        if let termion::event::MouseEvent::Press(termion::event::MouseButton::Left, x, y) =
+            mouse_event
+        {
+            let (x, y) = (x as usize, y as usize);
+            if self.status_input_active || y > self.get_sub_frame_height() + 1 {
+                return Task::None;
             }
-            View::FileTree => todo!(),
-            View::Both(FocusComponent::FileTree) => todo!(),
-        };
+            if y == 1 {
+                self.click_tab(x);
+                return Task::None;
+            }
+            // a click focuses the pane it lands on
+            let clicked = self
+                .layout
+                .rects(self.get_sub_frame_area())
+                .into_iter()
+                .find(|(_, rect)| rect.contains(x - 1, y - 1));
+            if let Some((id, _)) = clicked {
+                self.focus_pane(id);
+            }
+        }
+        if !self.is_tree_focused() {
+            let cell = self.get_doc_cell(&mouse_event);
+            self.docs[self.active_doc].process_mouse_event(&mouse_event, cell);
+        }
         Task::None
     }
 
-    fn process_command_event(&mut self, input: String) -> Task {
-        match self.view {
-            View::Doc | View::Both(FocusComponent::Doc) => {
-                self.docs[self.active_doc].process_command_input(input)
+    fn click_tab(&mut self, x: usize) {
+        let mut end = 0;
+        for (i, doc) in self.docs.iter().enumerate() {
+            // " title |"
+            end += text::width(&Self::get_tab_title(doc), self.config.general.tab_width) + 3;
+            if x <= end {
+                self.show_doc(i);
+                return;
             }
-            View::FileTree | View::Both(FocusComponent::FileTree) => todo!(),
         }
     }
 
-    fn update_cursor_from_curr_doc(&mut self) {
-        let col_offset = self.docs[self.active_doc].get_line_number_length();
-
-        let col_offset = match self.view {
-            View::Doc => col_offset + self.config.general.line_number_padding_right,
-            View::FileTree => todo!(),
-            View::Both(FocusComponent::Doc) => {
-                self.config.general.file_tree_width
-                    + col_offset
-                    + self.config.general.line_number_padding_right
-            }
-            View::Both(FocusComponent::FileTree) => todo!(),
+    // screen cell of the event relative to the text of the active doc
+    fn get_doc_cell(&self, mouse_event: &termion::event::MouseEvent) -> Position {
+        let (x, y) = match *mouse_event {
+            termion::event::MouseEvent::Press(_, x, y)
+            | termion::event::MouseEvent::Release(x, y)
+            | termion::event::MouseEvent::Hold(x, y) => (x as usize, y as usize),
         };
+        let rect = self.get_pane_rect(self.doc_pane);
+        let left = rect.x + self.docs[self.active_doc].get_gutter_width(&self.config);
+        Position {
+            row: y.saturating_sub(1 + rect.y),
+            col: x.saturating_sub(1 + left),
+        }
+    }
+
+    // apart from the quit prompt and the palette only docs ask for input, the
+    // answer goes to the active one
+    fn process_command_event(&mut self, input: String) -> Task {
+        if self.quit_pending {
+            return self.confirm_quit(&input);
+        }
+        if self.recovered_pending > 0 {
+            return self.confirm_recovered(&input);
+        }
+        if let Some(palette) = self.palette.take() {
+            return match palette.get_selected() {
+                Some(action) => self.docs[self.active_doc].process_action(action),
+                None => Task::None,
+            };
+        }
+        if let Some(finder) = self.finder.take() {
+            // with nothing matching, the input is taken as a path
+            return match finder.get_selected() {
+                Some(path) => Task::OpenDoc(path.to_string()),
+                None if !input.trim().is_empty() => Task::OpenDoc(input.trim().to_string()),
+                None => Task::None,
+            };
+        }
+        self.docs[self.active_doc].process_command_input(input)
+    }
+
+    fn process_command_change(&mut self, input: String) -> Task {
+        if let Some(palette) = &mut self.palette {
+            palette.filter(&input);
+            return Task::None;
+        }
+        if let Some(finder) = &mut self.finder {
+            finder.filter(&input);
+            return Task::None;
+        }
+        self.docs[self.active_doc].process_command_change(input)
+    }
 
-        let doc_cursor = self.docs[self.active_doc].cursor_pos;
-        let doc_offset = self.docs[self.active_doc].offset;
+    fn process_command_cancel(&mut self) -> Task {
+        if self.quit_pending {
+            return self.confirm_quit("c");
+        }
+        if self.recovered_pending > 0 {
+            return self.confirm_recovered("k");
+        }
+        if self.palette.take().is_some() || self.finder.take().is_some() {
+            return Task::None;
+        }
+        self.docs[self.active_doc].process_command_cancel()
+    }
+
+    fn update_cursor_from_curr_doc(&mut self) {
+        let rect = self.get_pane_rect(self.doc_pane);
+        let col_offset = rect.x + self.docs[self.active_doc].get_gutter_width(&self.config);
 
-        let row_offset: usize = 1;
+        let doc_cursor = self.docs[self.active_doc].get_cursor_view_pos();
 
         self.cursor_pos = Position {
-            row: row_offset + doc_cursor.row + 1 - doc_offset.row,
-            col: col_offset + doc_cursor.col + 1 - doc_offset.col,
+            row: rect.y + doc_cursor.row + 1,
+            col: col_offset + doc_cursor.col + 1,
         };
     }
 
     fn update(&mut self) {
         self.terminal.sync_terminal_size();
-        self.terminal.clear();
-        self.terminal.set_cursor_pos(1, 1);
 
         self.render();
 
-        self.terminal
-            .set_cursor_pos(self.cursor_pos.row, self.cursor_pos.col);
+        let status = &self.docs[self.active_doc].command_status;
+        if *status != self.shown_status {
+            self.shown_status = status.clone();
+            match status.is_empty() {
+                true => self.events.cancel(Timer::ClearStatus),
+                false => self.events.set_timeout(Timer::ClearStatus, STATUS_TIMEOUT),
+            }
+        }
+
+        // kept on the screen, however small it got
+        let size = self.terminal.size;
+        self.terminal.set_cursor_pos(
+            self.cursor_pos.row.clamp(1, size.height.max(1)),
+            self.cursor_pos.col.clamp(1, size.width.max(1)),
+        );
         self.terminal.flush();
     }
 
@@ -186,25 +485,49 @@ impl Editor {
         // update cursor based on view after render
         if self.status_input_active {
             self.update_cursor_from_command_line();
+        } else if self.is_tree_focused() {
+            self.update_cursor_from_file_tree();
         } else {
-            match self.view {
-                View::Doc | View::Both(FocusComponent::Doc) => self.update_cursor_from_curr_doc(),
-                View::FileTree | View::Both(FocusComponent::FileTree) => todo!(),
-            }
+            self.update_cursor_from_curr_doc();
         }
     }
 
+    fn update_cursor_from_file_tree(&mut self) {
+        let rect = self.get_pane_rect(self.focused_pane);
+        self.cursor_pos = Position {
+            row: rect.y + 1 + self.file_tree.get_cursor_row(),
+            col: rect.x + 1,
+        };
+    }
+
     fn update_cursor_from_command_line(&mut self) {
         self.cursor_pos.row = self.terminal.size.height;
-        self.cursor_pos.col = self.status_line.get_cursor_with_prefix();
+        self.cursor_pos.col = self
+            .status_line
+            .get_cursor_with_prefix(self.config.general.tab_width);
     }
 
     fn render(&mut self) {
         let mut frames: Vec<String> = Vec::with_capacity(max(3, self.terminal.size.height));
 
-        frames.push(self.render_header());
+        frames.push(self.render_doc_tabs());
 
         let mut doc_frame = self.get_sub_frame();
+        if let Some(palette) = &self.palette {
+            // drawn over the bottom rows, right above the prompt
+            let rows = palette.render(self.terminal.size.width, doc_frame.len(), &self.config);
+            let start = doc_frame.len() - rows.len();
+            doc_frame.splice(start.., rows);
+        }
+        if let Some(finder) = &mut self.finder {
+            let height = doc_frame.len();
+            doc_frame = finder.render(self.terminal.size.width, height, &self.config);
+        }
+        let completions = self
+            .status_line
+            .render_completions(self.terminal.size.width, &self.config);
+        let start = doc_frame.len().saturating_sub(completions.len());
+        doc_frame.splice(start.., completions.into_iter().take(doc_frame.len()));
         frames.append(&mut doc_frame);
 
         // should be after sub frame rendering to get correct cursor & offset
@@ -213,13 +536,13 @@ impl Editor {
         let command_render = self.render_command_line();
         frames.push(command_render);
 
-        self.terminal.print(frames.join("\r\n"));
+        self.terminal.draw(&frames);
     }
 
-    fn render_header(&self) -> String {
-        match self.view {
-            View::Doc | View::Both(FocusComponent::Doc) => self.render_doc_tabs(),
-            View::FileTree | View::Both(FocusComponent::FileTree) => todo!(),
+    fn get_tab_title(doc: &Doc) -> String {
+        match doc.is_dirty() {
+            true => format!("{}*", doc.get_title()),
+            false => doc.get_title(),
         }
     }
 
@@ -227,7 +550,7 @@ impl Editor {
         let mut tabs = String::new();
 
         for (i, doc) in self.docs.iter().enumerate() {
-            let title = doc.get_title();
+            let title = Self::get_tab_title(doc);
 
             if i == self.active_doc {
                 tabs.push_str(format!(" {} |", title).as_str());
@@ -239,101 +562,368 @@ impl Editor {
     }
 
     fn render_command_line(&mut self) -> String {
+        if !self.pending_keys.is_empty() && !self.status_line.is_taking_input() {
+            return format!("{} -", keymap::format_keys(&self.pending_keys));
+        }
         if !self.status_line.is_taking_input() {
-            match self.view {
-                View::Doc | View::Both(FocusComponent::Doc) => self
-                    .status_line
-                    .set_status(self.docs[self.active_doc].command_status.clone()),
-                View::FileTree | View::Both(FocusComponent::FileTree) => todo!(),
-            };
+            self.status_line
+                .set_status(self.docs[self.active_doc].command_status.clone());
         }
-        self.status_line.render()
+        self.status_line.render(&self.config)
     }
 
     fn render_status_line(&mut self) -> String {
         self.update_cursor_pos();
 
-        match self.view {
-            View::Doc | View::Both(FocusComponent::Doc) => {
+        match self.is_tree_focused() {
+            false => {
                 let active_doc = &self.docs[self.active_doc];
-                let status = format!(
-                    "{}/{} | {}",
+                let mut status = format!(
+                    "{} | {} | {}/{} | {}",
+                    active_doc.get_language(),
+                    active_doc.get_line_ending(),
                     active_doc.cursor_pos.row + 1,
-                    active_doc.lines.len(),
+                    active_doc.line_count(),
                     active_doc.cursor_pos.col,
                 );
-                format!(
-                    "{}{}",
-                    " ".repeat(self.terminal.size.width.saturating_sub(status.len()) - 1),
-                    status,
-                )
+                if self.config.general.vim_mode {
+                    status = format!("{} | {}", active_doc.get_mode_name(), status);
+                }
+                self.align_right(&status)
             }
-            _ => {
-                format!(
-                    "status line ({}/{}) ({}/{})",
-                    self.cursor_pos.row,
-                    self.terminal.size.height,
-                    self.cursor_pos.col,
-                    self.terminal.size.width
-                )
+            true => {
+                let selected = self.file_tree.get_selected_path().unwrap_or_default();
+                self.align_right(&selected)
             }
         }
     }
 
-    fn get_sub_frame(&mut self) -> Vec<String> {
-        match self.view {
-            View::Doc => self.render_doc_view(),
-            View::FileTree => self.render_file_tree_view(),
-            View::Both(_) => todo!("both rendering needs to be implemented"),
+    // one cell off the right edge, losing its start when it doesn't fit
+    fn align_right(&self, status: &str) -> String {
+        let room = self.terminal.size.width.saturating_sub(1);
+        let tab_width = self.config.general.tab_width;
+        let status_width = text::width(status, tab_width);
+        match status_width <= room {
+            true => format!("{}{}", " ".repeat(room - status_width), status),
+            false => text::sub_cells(
+                status,
+                status_width - room,
+                room,
+                &[],
+                &self.config.theme,
+                tab_width,
+            ),
         }
     }
 
-    fn render_doc_view(&mut self) -> Vec<String> {
-        self.render_doc(Size {
-            height: max(3, self.terminal.size.height) - 3,
+    fn get_sub_frame_height(&self) -> usize {
+        max(3, self.terminal.size.height) - 3
+    }
+
+    // the screen between the tabs and the status line
+    fn get_sub_frame_area(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 1,
             width: self.terminal.size.width,
-        })
+            height: self.get_sub_frame_height(),
+        }
     }
-    fn render_file_tree_view(&self) -> Vec<String> {
-        todo!()
+
+    fn get_pane_rect(&self, id: PaneId) -> Rect {
+        self.layout
+            .rects(self.get_sub_frame_area())
+            .into_iter()
+            .find_map(|(pane, rect)| (pane == id).then_some(rect))
+            .unwrap_or(self.get_sub_frame_area())
     }
 
-    fn render_doc(&mut self, size: Size) -> Vec<String> {
-        let mut frame = self.docs[self.active_doc].render(&size, &self.config);
+    fn get_sub_frame(&mut self) -> Vec<String> {
+        let area = self.get_sub_frame_area();
+        let mut frames: HashMap<PaneId, Vec<String>> = self
+            .layout
+            .rects(area)
+            .into_iter()
+            .map(|(id, rect)| (id, self.render_pane(id, rect)))
+            .collect();
+        self.layout
+            .render(area, &mut |id, _| frames.remove(&id).unwrap_or_default())
+    }
+
+    fn render_pane(&mut self, id: PaneId, rect: Rect) -> Vec<String> {
+        let size = Size {
+            height: rect.height,
+            width: rect.width,
+        };
+        match self.panes.get(&id) {
+            Some(Pane::FileTree) => {
+                self.file_tree
+                    .render(&size, id == self.focused_pane, &self.config)
+            }
+            Some(Pane::Doc(doc, _)) if id == self.doc_pane => self.render_doc(*doc, size),
+            // the doc holds the view of the doc pane, this one is swapped in
+            // for the render and kept aside again
+            Some(&Pane::Doc(doc, view)) => {
+                let live = self.docs[doc].get_view();
+                self.docs[doc].set_view(view);
+                let frame = self.render_doc(doc, size);
+                self.panes
+                    .insert(id, Pane::Doc(doc, self.docs[doc].get_view()));
+                self.docs[doc].set_view(live);
+                frame
+            }
+            None => vec![" ".repeat(size.width); size.height],
+        }
+    }
+
+    fn render_doc(&mut self, doc: usize, size: Size) -> Vec<String> {
+        let mut frame = self.docs[doc].render(&size, &self.config);
 
         while frame.len() < size.height {
-            frame.push("~".to_string());
+            frame.push(format!("~{}", " ".repeat(size.width.saturating_sub(1))));
         }
 
         frame
     }
 }
 
+// panes
+impl Editor {
+    fn is_tree_focused(&self) -> bool {
+        matches!(self.panes.get(&self.focused_pane), Some(Pane::FileTree))
+    }
+
+    fn get_tree_pane(&self) -> Option<PaneId> {
+        self.panes
+            .iter()
+            .find_map(|(id, pane)| matches!(pane, Pane::FileTree).then_some(*id))
+    }
+
+    // panes showing a doc, in screen order
+    fn get_doc_panes(&self) -> Vec<PaneId> {
+        self.layout
+            .panes()
+            .into_iter()
+            .filter(|id| matches!(self.panes.get(id), Some(Pane::Doc(..))))
+            .collect()
+    }
+
+    fn add_pane(&mut self, pane: Pane) -> PaneId {
+        let id = self.next_pane;
+        self.next_pane += 1;
+        self.panes.insert(id, pane);
+        id
+    }
+
+    fn focus_pane(&mut self, id: PaneId) {
+        self.focused_pane = id;
+        let Some(&Pane::Doc(doc, view)) = self.panes.get(&id) else {
+            return;
+        };
+        if id == self.doc_pane {
+            return;
+        }
+        // the view of the pane left behind is kept aside
+        if let Some(Pane::Doc(old_doc, old_view)) = self.panes.get_mut(&self.doc_pane) {
+            *old_view = self.docs[*old_doc].get_view();
+        }
+        self.docs[doc].set_view(view);
+        self.active_doc = doc;
+        self.doc_pane = id;
+    }
+
+    // shows doc `idx` in the doc pane and focuses it
+    fn show_doc(&mut self, idx: usize) {
+        self.active_doc = idx;
+        self.panes
+            .insert(self.doc_pane, Pane::Doc(idx, self.docs[idx].get_view()));
+        self.focused_pane = self.doc_pane;
+    }
+
+    fn cycle_pane(&mut self, forward: bool) {
+        let panes = self.layout.panes();
+        let idx = panes
+            .iter()
+            .position(|id| *id == self.focused_pane)
+            .unwrap_or(0);
+        let next = match forward {
+            true => (idx + 1) % panes.len(),
+            false => (idx + panes.len() - 1) % panes.len(),
+        };
+        self.focus_pane(panes[next]);
+    }
+
+    fn split_pane(&mut self, axis: Axis) {
+        let rect = self.get_pane_rect(self.doc_pane);
+        let fits = match axis {
+            Axis::Horizontal => rect.width > 2 * MIN_PANE_WIDTH,
+            Axis::Vertical => rect.height > 2 * MIN_PANE_HEIGHT,
+        };
+        if !fits {
+            self.docs[self.active_doc].set_command_status("Pane is too small to split".to_string());
+            return;
+        }
+        let view = self.docs[self.active_doc].get_view();
+        let new = self.add_pane(Pane::Doc(self.active_doc, view));
+        self.layout.split(self.doc_pane, new, axis);
+        self.focus_pane(new);
+    }
+
+    fn close_pane(&mut self) {
+        if self.is_tree_focused() {
+            self.toggle_file_tree();
+            return;
+        }
+        let doc_panes = self.get_doc_panes();
+        if doc_panes.len() == 1 {
+            self.docs[self.active_doc].set_command_status("Last pane is not closed".to_string());
+            return;
+        }
+        let idx = doc_panes
+            .iter()
+            .position(|id| *id == self.doc_pane)
+            .unwrap_or(0);
+        self.layout.remove(self.doc_pane);
+        self.panes.remove(&self.doc_pane);
+        // the pane before it takes the focus, or the one after for the first
+        let next = match idx {
+            0 => doc_panes[1],
+            idx => doc_panes[idx - 1],
+        };
+        self.focus_pane(next);
+    }
+
+    fn resize_pane(&mut self, grow: bool) {
+        let area = self.get_sub_frame_area();
+        if !self.layout.resize(self.focused_pane, grow, area) {
+            self.docs[self.active_doc].set_command_status("No split to resize".to_string());
+        }
+    }
+
+    fn toggle_file_tree(&mut self) {
+        match self.get_tree_pane() {
+            Some(id) => {
+                self.layout.remove(id);
+                self.panes.remove(&id);
+                self.focused_pane = self.doc_pane;
+            }
+            None => {
+                self.file_tree.refresh();
+                let id = self.add_pane(Pane::FileTree);
+                // the width set in the config counts the separator
+                let width = self.config.general.file_tree_width.saturating_sub(1);
+                self.layout.add_left(id, width);
+                self.focused_pane = id;
+            }
+        }
+    }
+
+    fn switch_focus(&mut self) {
+        if self.is_tree_focused() {
+            self.focused_pane = self.doc_pane;
+        } else if let Some(id) = self.get_tree_pane() {
+            self.focused_pane = id;
+        }
+    }
+
+    // asks about the swap file left for the active doc
+    fn check_swap(&mut self) -> Task {
+        match &self.swap_dir {
+            Some(dir) => self.docs[self.active_doc].check_swap(dir),
+            None => Task::None,
+        }
+    }
+
+    fn confirm_recovered(&mut self, choice: &str) -> Task {
+        let recovered = mem::take(&mut self.recovered_pending);
+        let first = self.docs.len() - recovered;
+        match choice {
+            "d" => {
+                if first == 0 {
+                    self.docs.push(Doc::new());
+                }
+                // removes their swap files too
+                for idx in (first..first + recovered).rev() {
+                    self.close_doc(idx);
+                }
+                self.docs[self.active_doc]
+                    .set_command_status(format!("Discarded {} recovered doc(s)", recovered));
+            }
+            _ => {
+                for doc in &mut self.docs[first..] {
+                    match (&self.swap_dir, self.config.general.swap_interval > 0) {
+                        // written again, stating this editor as owner
+                        (Some(dir), true) => doc.write_swap(dir),
+                        _ => doc.remove_swap(),
+                    }
+                }
+                self.docs[self.active_doc]
+                    .set_command_status(format!("Kept {} recovered doc(s)", recovered));
+            }
+        }
+        // the doc opened on startup is asked about once this is answered
+        self.check_swap()
+    }
+
+    // panes showing the closed doc show the one before it instead
+    fn close_doc(&mut self, idx: usize) {
+        self.docs.remove(idx).remove_swap();
+        if self.docs.is_empty() {
+            return;
+        }
+        let replacement = idx.saturating_sub(1);
+        for pane in self.panes.values_mut() {
+            if let Pane::Doc(doc, view) = pane {
+                if *doc == idx {
+                    *doc = replacement;
+                    *view = self.docs[replacement].get_view();
+                } else if *doc > idx {
+                    *doc -= 1;
+                }
+            }
+        }
+        if let Some(Pane::Doc(doc, _)) = self.panes.get(&self.doc_pane) {
+            self.active_doc = *doc;
+        }
+        self.focused_pane = self.doc_pane;
+    }
+}
+
 // file related operations
 impl Editor {
     fn new_document(&mut self) {
         // Create a new document
         self.docs.push(Doc::new());
-        self.active_doc = self.docs.len() - 1;
-        self.view = match self.view {
-            View::Doc => View::Doc,
-            View::FileTree => View::Doc,
-            View::Both(_) => View::Both(FocusComponent::Doc),
-        }
+        self.show_doc(self.docs.len() - 1);
     }
     fn open_document(&mut self, file: Option<String>) {
         match file {
             Some(path) => {
                 // File was specified
-                if let Some(doc) = Doc::open(&path) {
+                if let Some(idx) = self
+                    .docs
+                    .iter()
+                    .position(|doc| doc.get_file_path() == Some(path.as_str()))
+                {
+                    self.show_doc(idx);
+                    self.recent.add(&path);
+                } else if let Some(doc) = Doc::open(&path) {
                     self.docs.push(doc);
-                    self.active_doc = self.docs.len() - 1;
-                    self.view = match self.view {
-                        View::Doc => View::Doc,
-                        View::FileTree => View::Doc,
-                        View::Both(_) => View::Both(FocusComponent::Doc),
-                    }
+                    self.show_doc(self.docs.len() - 1);
+                    self.recent.add(&path);
+                    let task = self.check_swap();
+                    self.process_task(task);
+                } else if Path::new(&path).exists() {
+                    self.docs[self.active_doc].set_command_status(format!("Cannot open {}", path));
                 } else {
+                    // like on the command line, a new file is created on save
+                    let mut doc = Doc::new();
+                    doc.set_file_path(path);
+                    doc.set_command_status("New named doc created".to_string());
+                    self.docs.push(doc);
+                    self.show_doc(self.docs.len() - 1);
+                    let task = self.check_swap();
+                    self.process_task(task);
                 }
             }
             None => {
@@ -349,39 +939,106 @@ impl Editor {
     fn process_task(&mut self, task: Task) {
         match task {
             Task::SetCommand(text) => self.process_set_command(text),
-            Task::AskInput(prefix) => self.ask_input(prefix),
+            Task::AskInput(prefix) => self.ask_input(prefix, false),
+            Task::AskPath(prefix) => self.ask_input(prefix, true),
+            Task::AskChoice(prefix) => self.ask_choice(prefix),
+            Task::CommandPalette => {
+                self.palette = Some(Palette::new(&self.config));
+                self.ask_input("Command".to_string(), false);
+            }
+            Task::FindFile => {
+                let root = env::current_dir().unwrap_or_else(|_| ".".into());
+                let finder = Finder::new(&root, self.recent.get_paths());
+                let root = finder.get_root().to_path_buf();
+                self.finder = Some(finder);
+                // a large tree takes a while, recent files are listed meanwhile
+                self.events
+                    .spawn(move || WorkerMessage::FilesFound(root.clone(), finder::walk(&root)));
+                self.ask_input("Open".to_string(), true);
+            }
             Task::None => {}
             Task::NewDoc => self.new_document(),
             Task::OpenDoc(path) => self.open_document(Some(path)),
-            Task::NextTab => {
-                self.active_doc = self.active_doc.saturating_add(1);
-                if self.active_doc >= self.docs.len() {
-                    self.active_doc = 0;
-                }
-            }
+            Task::NextTab => self.show_doc((self.active_doc + 1) % self.docs.len()),
             Task::PrevTab => {
-                if self.active_doc == 0 {
-                    self.active_doc = self.docs.len() - 1;
-                } else {
-                    self.active_doc = self.active_doc.saturating_sub(1);
+                self.show_doc((self.active_doc + self.docs.len() - 1) % self.docs.len())
+            }
+            Task::ToggleFileTree => self.toggle_file_tree(),
+            Task::SwitchFocus => self.switch_focus(),
+            Task::SplitPane(axis) => self.split_pane(axis),
+            Task::ClosePane => self.close_pane(),
+            Task::NextPane => self.cycle_pane(true),
+            Task::PrevPane => self.cycle_pane(false),
+            Task::GrowPane => self.resize_pane(true),
+            Task::ShrinkPane => self.resize_pane(false),
+            // the doc already asked about unsaved changes
+            Task::CloseCurrentTab => self.close_doc(self.active_doc),
+            Task::Quit => self.request_quit(),
+            Task::Copy(register, text) => {
+                if register == DEFAULT_REGISTER && self.config.general.osc52_clipboard {
+                    self.terminal.print(clipboard::osc52(&text));
                 }
+                self.clipboard.set(register, text);
+                self.docs[self.active_doc].set_command_status(match register {
+                    DEFAULT_REGISTER => "Copied".to_string(),
+                    _ => format!("Copied to register {}", register),
+                });
             }
-            Task::CloseCurrentTab => {
-                if self.docs[self.active_doc].can_close() {
-                    self.docs.remove(self.active_doc);
-                    self.active_doc = self.active_doc.saturating_sub(1);
-                } else {
-                    self.status_line
-                        .set_status("Can't close this tab".to_string());
+            Task::Paste(register) => match self.clipboard.get(register) {
+                Some(text) => self.docs[self.active_doc].paste(text),
+                None => self.docs[self.active_doc]
+                    .set_command_status(format!("Register {} is empty", register)),
+            },
+            Task::ShowDiff(title, diff) => {
+                self.docs.push(Doc::scratch(title, &diff));
+                self.show_doc(self.docs.len() - 1);
+            }
+        }
+    }
+    fn request_quit(&mut self) {
+        let dirty = self.docs.iter().filter(|doc| doc.is_dirty()).count();
+        if dirty == 0 {
+            self.quit = true;
+            return;
+        }
+        self.quit_pending = true;
+        self.ask_choice(format!(
+            "{} doc(s) with unsaved changes. (s)ave all (d)iscard (c)ancel",
+            dirty
+        ));
+    }
+    fn confirm_quit(&mut self, choice: &str) -> Task {
+        self.quit_pending = false;
+        match choice {
+            "s" => {
+                for doc in self.docs.iter_mut().filter(|doc| doc.is_dirty()) {
+                    doc.save();
+                }
+                // docs without a path, or failing to save, stay open
+                match self.docs.iter().position(|doc| doc.is_dirty()) {
+                    Some(idx) => {
+                        self.show_doc(idx);
+                        let title = self.docs[idx].get_title();
+                        self.docs[idx]
+                            .set_command_status(format!("{} is not saved, quit cancelled", title));
+                    }
+                    None => self.quit = true,
                 }
             }
+            "d" => self.quit = true,
+            _ => self.docs[self.active_doc].set_command_status("Quit cancelled".to_string()),
         }
+        Task::None
     }
     fn process_set_command(&mut self, text: String) {
         self.status_line.set_status(text);
     }
-    fn ask_input(&mut self, prefix: String) {
-        self.status_line.take_input(prefix);
+    fn ask_input(&mut self, prefix: String, completes_paths: bool) {
+        self.status_line.take_input(prefix, completes_paths);
+        self.status_input_active = true;
+    }
+    fn ask_choice(&mut self, prefix: String) {
+        self.status_line.take_choice(prefix);
         self.status_input_active = true;
     }
 }
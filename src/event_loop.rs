@@ -0,0 +1,129 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use termion::event::Event;
+
+/// Everything the editor reacts to, in the order it happened.
+pub enum EditorEvent {
+    Input(Event),
+    // the terminal changed size, the screen has to be drawn again
+    Resize,
+    // the terminal has no more input for now
+    InputClosed,
+    Timer(Timer),
+    Worker(WorkerMessage),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Timer {
+    // look for changes made to the open file by others
+    DiskCheck,
+    // the status message has been shown long enough
+    ClearStatus,
+    // write the unsaved changes to swap files
+    Autosave,
+}
+
+/// What a worker thread hands back when it is done.
+pub enum WorkerMessage {
+    // the files under a folder, for the finder opened on it
+    FilesFound(PathBuf, Vec<PathBuf>),
+}
+
+struct Scheduled {
+    timer: Timer,
+    at: Instant,
+    // set for timers firing again and again
+    every: Option<Duration>,
+}
+
+/// One queue for terminal input, resizes, timers and worker messages. The
+/// terminal and workers send to it, the editor takes one event at a time.
+pub struct EventLoop {
+    sender: Sender<EditorEvent>,
+    receiver: Receiver<EditorEvent>,
+    timers: Vec<Scheduled>,
+}
+
+impl EventLoop {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            timers: vec![],
+        }
+    }
+
+    pub fn sender(&self) -> Sender<EditorEvent> {
+        self.sender.clone()
+    }
+
+    /// Fires `timer` once after `delay`, replacing it if it was set.
+    pub fn set_timeout(&mut self, timer: Timer, delay: Duration) {
+        self.schedule(timer, delay, None);
+    }
+
+    /// Fires `timer` every `period`, replacing it if it was set.
+    pub fn set_interval(&mut self, timer: Timer, period: Duration) {
+        self.schedule(timer, period, Some(period));
+    }
+
+    fn schedule(&mut self, timer: Timer, delay: Duration, every: Option<Duration>) {
+        self.cancel(timer);
+        self.timers.push(Scheduled {
+            timer,
+            at: Instant::now() + delay,
+            every,
+        });
+    }
+
+    pub fn cancel(&mut self, timer: Timer) {
+        self.timers.retain(|scheduled| scheduled.timer != timer);
+    }
+
+    /// Runs `work` on a thread of its own, its result comes back as an event.
+    pub fn spawn(&self, work: impl FnOnce() -> WorkerMessage + Send + 'static) {
+        let sender = self.sender();
+        thread::spawn(move || {
+            let _ = sender.send(EditorEvent::Worker(work()));
+        });
+    }
+
+    /// Waits for the next event. Timers due fire before anything queued.
+    pub fn next(&mut self) -> EditorEvent {
+        loop {
+            let next_timer = self
+                .timers
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, scheduled)| scheduled.at)
+                .map(|(i, scheduled)| (i, scheduled.at));
+            let Some((i, at)) = next_timer else {
+                // the loop holds a sender, so the channel stays open
+                return self.receiver.recv().unwrap_or(EditorEvent::InputClosed);
+            };
+            let now = Instant::now();
+            if at <= now {
+                let scheduled = &mut self.timers[i];
+                let timer = scheduled.timer;
+                match scheduled.every {
+                    Some(every) => scheduled.at = now + every,
+                    None => {
+                        self.timers.remove(i);
+                    }
+                }
+                return EditorEvent::Timer(timer);
+            }
+            match self.receiver.recv_timeout(at - now) {
+                Ok(event) => return event,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return EditorEvent::InputClosed,
+            }
+        }
+    }
+}
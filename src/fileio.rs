@@ -0,0 +1,128 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, File, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    os::unix::fs::{fchown, MetadataExt},
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
+};
+
+/// Replaces the file at `path` with `content` without ever leaving it half
+/// written: the content goes to a temp file next to it, which is synced and
+/// renamed over the original. Mode and ownership of the original are kept,
+/// with `backup` the original stays as `file~`.
+pub fn write_atomic(path: &str, content: &[u8], backup: bool) -> io::Result<()> {
+    // write through symlinks instead of replacing them
+    let target = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?
+        .to_string_lossy()
+        .to_string();
+    let original = fs::metadata(&target).ok();
+
+    let temp = dir.join(format!(".{}.{}.tmp", name, process::id()));
+    let result = write_temp(&temp, content, original.as_ref()).and_then(|_| {
+        if original.is_some() && backup {
+            fs::copy(&target, dir.join(format!("{}~", name)))
+                .map_err(|err| context("cannot write backup", err))?;
+        }
+        fs::rename(&temp, &target).map_err(|err| context("cannot replace file", err))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+        return result;
+    }
+    // makes the rename itself durable
+    if let Ok(dir) = File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn write_temp(temp: &Path, content: &[u8], original: Option<&fs::Metadata>) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp)
+        .map_err(|err| context("cannot create temp file", err))?;
+    file.write_all(content)
+        .map_err(|err| context("cannot write", err))?;
+    if let Some(original) = original {
+        file.set_permissions(original.permissions())
+            .map_err(|err| context("cannot set permissions", err))?;
+        // only root can give a file away, other users keep their own ownership
+        let _ = fchown(&file, Some(original.uid()), Some(original.gid()));
+    }
+    file.sync_all().map_err(|err| context("cannot sync", err))
+}
+
+fn context(what: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", what, err))
+}
+
+/// How a file differs from what was last loaded or saved.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiskChange {
+    Modified,
+    // deleted or moved away
+    Deleted,
+}
+
+/// What a file looked like when it was last loaded or saved.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl DiskState {
+    pub fn of(path: &str, content: &[u8]) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hash(content),
+        })
+    }
+
+    pub fn read(path: &str) -> Option<Self> {
+        Self::of(path, &fs::read(path).ok()?)
+    }
+
+    /// How the file on disk differs from `self`, a file touched without
+    /// changing its content only refreshes the stored mtime.
+    pub fn changed(&mut self, path: &str) -> Option<DiskChange> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Some(DiskChange::Deleted);
+            }
+            Err(_) => return None,
+        };
+        if metadata.modified().ok() == self.modified && metadata.len() == self.len {
+            return None;
+        }
+        match Self::read(path) {
+            Some(current) if current.hash == self.hash => {
+                *self = current;
+                None
+            }
+            Some(_) => Some(DiskChange::Modified),
+            None => None,
+        }
+    }
+}
+
+fn hash(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
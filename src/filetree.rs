@@ -1,3 +1,217 @@
-pub struct FileTree {}
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
-impl FileTree {}
+use termion::style;
+
+use crate::{
+    common::{Size, Task},
+    config::Config,
+    keymap::Action,
+    text,
+};
+
+struct Entry {
+    path: PathBuf,
+    name: String,
+    depth: usize,
+    is_dir: bool,
+    expanded: bool,
+}
+
+/// Directory listing shown next to the docs, rooted at the working directory.
+///
+/// `entries` holds only what is visible, in display order: a folder is
+/// followed by its children while it is expanded.
+pub struct FileTree {
+    root: PathBuf,
+    entries: Vec<Entry>,
+    selected: usize,
+    offset: usize,
+}
+
+impl FileTree {
+    pub fn new(root: PathBuf) -> Self {
+        let entries = Self::read_dir(&root, 0);
+        Self {
+            root,
+            entries,
+            selected: 0,
+            offset: 0,
+        }
+    }
+
+    // folders first, then files, both sorted by name
+    fn read_dir(path: &Path, depth: usize) -> Vec<Entry> {
+        let Ok(read_dir) = fs::read_dir(path) else {
+            return vec![];
+        };
+        let mut entries: Vec<Entry> = read_dir
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name() != ".git")
+            .map(|entry| Entry {
+                path: entry.path(),
+                name: entry.file_name().to_string_lossy().to_string(),
+                depth,
+                is_dir: entry.path().is_dir(),
+                expanded: false,
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            b.is_dir
+                .cmp(&a.is_dir)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+        entries
+    }
+
+    pub fn get_selected_path(&self) -> Option<String> {
+        self.entries
+            .get(self.selected)
+            .map(|entry| entry.path.display().to_string())
+    }
+
+    /// Row of the selected entry inside the rendered frame.
+    pub fn get_cursor_row(&self) -> usize {
+        self.selected - self.offset
+    }
+
+    pub fn process_action(&mut self, action: Action) -> Task {
+        match action {
+            Action::TreeUp => self.selected = self.selected.saturating_sub(1),
+            Action::TreeDown if self.selected + 1 < self.entries.len() => self.selected += 1,
+            Action::TreeFirst => self.selected = 0,
+            Action::TreeLast => self.selected = self.entries.len().saturating_sub(1),
+            Action::TreeExpand => self.expand(),
+            Action::TreeCollapse => self.collapse(),
+            Action::TreeOpen => return self.open_selected(),
+            Action::TreeRefresh => self.refresh(),
+            _ => {}
+        }
+        Task::None
+    }
+
+    fn open_selected(&mut self) -> Task {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return Task::None;
+        };
+        if !entry.is_dir {
+            return Task::OpenDoc(entry.path.display().to_string());
+        }
+        if entry.expanded {
+            self.collapse();
+        } else {
+            self.expand();
+        }
+        Task::None
+    }
+
+    fn expand(&mut self) {
+        let Some(entry) = self.entries.get_mut(self.selected) else {
+            return;
+        };
+        if !entry.is_dir || entry.expanded {
+            return;
+        }
+        entry.expanded = true;
+        let children = Self::read_dir(&entry.path, entry.depth + 1);
+        let at = self.selected + 1;
+        self.entries.splice(at..at, children);
+    }
+
+    // folds the selected folder, or moves to the parent folder
+    fn collapse(&mut self) {
+        let Some(entry) = self.entries.get(self.selected) else {
+            return;
+        };
+        if entry.is_dir && entry.expanded {
+            let depth = entry.depth;
+            let end = self.entries[self.selected + 1..]
+                .iter()
+                .position(|child| child.depth <= depth)
+                .map_or(self.entries.len(), |idx| self.selected + 1 + idx);
+            self.entries.drain(self.selected + 1..end);
+            self.entries[self.selected].expanded = false;
+        } else if entry.depth > 0 {
+            let depth = entry.depth;
+            if let Some(parent) = self.entries[..self.selected]
+                .iter()
+                .rposition(|parent| parent.depth < depth)
+            {
+                self.selected = parent;
+            }
+        }
+    }
+
+    /// Reads the listing again, keeping expanded folders open.
+    pub fn refresh(&mut self) {
+        let expanded: Vec<PathBuf> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.expanded)
+            .map(|entry| entry.path.clone())
+            .collect();
+        let selected = self.get_selected_path();
+
+        self.entries = Self::read_dir(&self.root, 0);
+        let mut idx = 0;
+        while idx < self.entries.len() {
+            if expanded.contains(&self.entries[idx].path) {
+                self.selected = idx;
+                self.expand();
+            }
+            idx += 1;
+        }
+        self.selected = selected
+            .and_then(|selected| {
+                self.entries
+                    .iter()
+                    .position(|entry| entry.path.display().to_string() == selected)
+            })
+            .unwrap_or(0)
+            .min(self.entries.len().saturating_sub(1));
+    }
+
+    /// Rows exactly `size.width` cells wide.
+    pub fn render(&mut self, size: &Size, focused: bool, config: &Config) -> Vec<String> {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        }
+        if size.height > 0 && self.selected >= self.offset + size.height {
+            self.offset = self.selected + 1 - size.height;
+        }
+
+        let mut frame = Vec::with_capacity(size.height);
+        for (i, entry) in self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(size.height)
+        {
+            let marker = match (entry.is_dir, entry.expanded) {
+                (true, true) => "▾ ",
+                (true, false) => "▸ ",
+                (false, _) => "  ",
+            };
+            let label = format!("{}{}{}", "  ".repeat(entry.depth), marker, entry.name);
+            let tab_width = config.general.tab_width;
+            let label = text::sub_cells(&label, 0, size.width, &[], &config.theme, tab_width);
+            let padding = " ".repeat(size.width.saturating_sub(text::width(&label, tab_width)));
+
+            let prefix = if i == self.selected && focused {
+                style::Invert.to_string()
+            } else if entry.is_dir {
+                style::Bold.to_string()
+            } else {
+                String::new()
+            };
+            frame.push(format!("{}{}{}{}", prefix, label, padding, style::Reset));
+        }
+        while frame.len() < size.height {
+            frame.push(" ".repeat(size.width));
+        }
+        frame
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::Read,
+    mem,
+    path::{Path, PathBuf},
+};
+
+use termion::style;
+
+use crate::{
+    buffer::Buffer,
+    config::Config,
+    fuzzy,
+    gitignore::{self, Gitignore},
+    syntax::{Highlighter, Syntax},
+    text,
+};
+
+// a walk stops there instead of hanging on a huge tree
+const MAX_FILES: usize = 50_000;
+// rows of the list at most, the rest of the screen shows the preview
+const MAX_ROWS: usize = 10;
+const PREVIEW_BYTES: u64 = 64 * 1024;
+// a hit in the file name beats the same hit spread over the folders
+const NAME_BONUS: i64 = 24;
+
+struct Candidate {
+    // as shown and opened, relative to the working directory when inside it
+    path: String,
+    // position in the recent files, 0 for the last opened
+    recent: Option<usize>,
+}
+
+struct Preview {
+    path: String,
+    buffer: Buffer,
+    highlighter: Highlighter,
+    // set instead of the buffer for files that can't be shown
+    message: Option<&'static str>,
+}
+
+/// Files of the project filtered by the status line input, with a preview
+/// of the selected one.
+pub struct Finder {
+    // folder the files are looked for in
+    root: PathBuf,
+    candidates: Vec<Candidate>,
+    // last filter input, applied again when files are added
+    input: String,
+    // indices into candidates matching the input, best first
+    matches: Vec<usize>,
+    selected: usize,
+    preview: Option<Preview>,
+}
+
+impl Finder {
+    /// A finder listing the recent files, the files under `root` are added
+    /// once a worker has found them.
+    pub fn new(root: &Path, recent: &[PathBuf]) -> Self {
+        // recent paths are canonical, files walked from a symlinked root
+        // would neither match nor be shown relative to it
+        let root = &fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        let candidates: Vec<Candidate> = recent
+            .iter()
+            .filter(|path| path.is_file())
+            .enumerate()
+            .map(|(i, path)| Candidate {
+                path: Self::display_path(root, path),
+                recent: Some(i),
+            })
+            .collect();
+        let mut finder = Self {
+            root: root.to_path_buf(),
+            matches: (0..candidates.len()).collect(),
+            candidates,
+            input: String::new(),
+            selected: 0,
+            preview: None,
+        };
+        finder.load_preview();
+        finder
+    }
+
+    /// The canonical folder the files are looked for in.
+    pub fn get_root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Adds the files found under the root after the recent ones, keeping
+    /// the selected file selected.
+    pub fn add_files(&mut self, mut files: Vec<PathBuf>) {
+        files.sort();
+        // walked paths are unique, only recent files can be there twice
+        let recent: HashSet<String> = self
+            .candidates
+            .iter()
+            .filter(|candidate| candidate.recent.is_some())
+            .map(|candidate| candidate.path.clone())
+            .collect();
+        for path in files {
+            let path = Self::display_path(&self.root, &path);
+            if !recent.contains(&path) {
+                self.candidates.push(Candidate { path, recent: None });
+            }
+        }
+        let selected = self.get_selected().map(str::to_string);
+        let input = mem::take(&mut self.input);
+        self.filter(&input);
+        if let Some(selected) = selected {
+            self.selected = self
+                .matches
+                .iter()
+                .position(|idx| self.candidates[*idx].path == selected)
+                .unwrap_or(0);
+            self.load_preview();
+        }
+    }
+
+    fn display_path(root: &Path, path: &Path) -> String {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    pub fn filter(&mut self, input: &str) {
+        self.input = input.to_string();
+        if input.trim().is_empty() {
+            self.matches = (0..self.candidates.len()).collect();
+        } else {
+            let mut scored: Vec<(i64, usize)> = self
+                .candidates
+                .iter()
+                .enumerate()
+                .filter_map(|(i, candidate)| Some((Self::score(input, candidate)?, i)))
+                .collect();
+            // stable, so equal scores keep recent files and then the path order
+            scored.sort_by_key(|(score, _)| -score);
+            self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        }
+        self.selected = 0;
+        self.load_preview();
+    }
+
+    fn score(input: &str, candidate: &Candidate) -> Option<i64> {
+        let path_score = fuzzy::score(input, &candidate.path)?;
+        let name = candidate.path.rsplit('/').next().unwrap_or_default();
+        let name_score = fuzzy::score(input, name).map_or(i64::MIN, |score| score + NAME_BONUS);
+        let recent_bonus = candidate.recent.map_or(0, |i| 16 - i.min(16) as i64);
+        Some(path_score.max(name_score) + recent_bonus)
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected = match self.selected {
+            0 => self.matches.len().saturating_sub(1),
+            selected => selected - 1,
+        };
+        self.load_preview();
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = match self.selected + 1 < self.matches.len() {
+            true => self.selected + 1,
+            false => 0,
+        };
+        self.load_preview();
+    }
+
+    pub fn get_selected(&self) -> Option<&str> {
+        let idx = self.matches.get(self.selected)?;
+        Some(&self.candidates[*idx].path)
+    }
+
+    fn load_preview(&mut self) {
+        let Some(path) = self.get_selected().map(str::to_string) else {
+            self.preview = None;
+            return;
+        };
+        if self
+            .preview
+            .as_ref()
+            .is_some_and(|preview| preview.path == path)
+        {
+            return;
+        }
+        let mut bytes = vec![];
+        let read =
+            fs::File::open(&path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut bytes));
+        let message = match read {
+            Err(_) => Some("cannot read file"),
+            Ok(_) if bytes.contains(&0) => Some("binary file"),
+            Ok(_) => None,
+        };
+        // a multibyte character cut by the read limit is dropped
+        let content = String::from_utf8_lossy(&bytes);
+        let lines = match message {
+            Some(_) => vec![],
+            None => content.lines().map(str::to_string).collect(),
+        };
+        self.preview = Some(Preview {
+            highlighter: Highlighter::new(Syntax::for_path(&path)),
+            buffer: Buffer::from_lines(lines),
+            path,
+            message,
+        });
+    }
+
+    /// Rows drawn over the screen, the preview on top and the list right
+    /// above the prompt, `height` of them.
+    pub fn render(&mut self, width: usize, height: usize, config: &Config) -> Vec<String> {
+        let list_rows = self.matches.len().clamp(1, MAX_ROWS).min(height);
+        let mut frame = self.render_preview(width, height - list_rows, config);
+        frame.extend(self.render_list(width, list_rows, config));
+        frame
+    }
+
+    fn render_preview(&mut self, width: usize, height: usize, config: &Config) -> Vec<String> {
+        let mut frame = Vec::with_capacity(height);
+        if height == 0 {
+            return frame;
+        }
+        let title = match &self.preview {
+            Some(preview) => format!("── {} ", preview.path),
+            None => String::new(),
+        };
+        let tab_width = config.general.tab_width;
+        let title = text::sub_cells(&title, 0, width, &[], &config.theme, tab_width);
+        let rule = "─".repeat(width.saturating_sub(text::width(&title, tab_width)));
+        frame.push(format!("{}{}{}{}", style::Faint, title, rule, style::Reset));
+
+        if let Some(preview) = &mut self.preview {
+            if let Some(message) = preview.message {
+                frame.push(format!("{} {}{}", style::Faint, message, style::Reset));
+            }
+            for row in 0..preview
+                .buffer
+                .len_lines()
+                .min(height.saturating_sub(frame.len()))
+            {
+                let line = preview.buffer.line(row);
+                let spans = preview.highlighter.spans(&preview.buffer, row, line);
+                let line = text::sub_cells(line, 0, width, &spans, &config.theme, tab_width);
+                frame.push(format!("{}{}", line, style::Reset));
+            }
+        }
+        while frame.len() < height {
+            frame.push(String::new());
+        }
+        frame.truncate(height);
+        frame
+    }
+
+    fn render_list(&self, width: usize, rows: usize, config: &Config) -> Vec<String> {
+        if rows == 0 {
+            return vec![];
+        }
+        let tab_width = config.general.tab_width;
+        if self.matches.is_empty() {
+            let label = text::sub_cells(
+                " No matching file, Enter opens the typed path",
+                0,
+                width,
+                &[],
+                &config.theme,
+                tab_width,
+            );
+            let padding = " ".repeat(width.saturating_sub(text::width(&label, tab_width)));
+            return vec![format!(
+                "{}{}{}{}",
+                style::Faint,
+                label,
+                padding,
+                style::Reset
+            )];
+        }
+        let offset = (self.selected + 1).saturating_sub(rows);
+        self.matches
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(rows)
+            .map(|(i, idx)| {
+                let candidate = &self.candidates[*idx];
+                let label = format!(" {}", candidate.path);
+                let label = text::sub_cells(&label, 0, width, &[], &config.theme, tab_width);
+                let padding = " ".repeat(width.saturating_sub(text::width(&label, tab_width)));
+                let prefix = if i == self.selected {
+                    style::Invert.to_string()
+                } else if candidate.recent.is_some() {
+                    style::Bold.to_string()
+                } else {
+                    String::new()
+                };
+                format!("{}{}{}{}", prefix, label, padding, style::Reset)
+            })
+            .collect()
+    }
+}
+
+/// Files under `root`, leaving out what `.gitignore` files ignore and the
+/// `.git` folder. Symlinked folders are not followed.
+pub fn walk(root: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    // started inside a repository, its ignore files above root apply too
+    let mut gitignores = gitignore::ancestors(root);
+    // folders to read, with how many gitignores apply to them
+    let mut stack: Vec<(PathBuf, usize)> = vec![(root.to_path_buf(), gitignores.len())];
+    while let Some((dir, depth)) = stack.pop() {
+        gitignores.truncate(depth);
+        gitignores.extend(Gitignore::load(&dir));
+        let Ok(read_dir) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read_dir.filter_map(Result::ok) {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            let is_dir = file_type.is_dir();
+            if entry.file_name() == ".git" || gitignore::is_ignored(&gitignores, &path, is_dir) {
+                continue;
+            }
+            if is_dir {
+                stack.push((path, gitignores.len()));
+            } else if path.is_file() {
+                files.push(path);
+                if files.len() >= MAX_FILES {
+                    return files;
+                }
+            }
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, os::unix::fs::symlink, process};
+
+    use super::*;
+
+    #[test]
+    fn symlinked_root() {
+        let dir = env::temp_dir().join(format!("kaelix-finder-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("real")).unwrap();
+        fs::write(dir.join("real/a.txt"), "a\n").unwrap();
+        symlink(dir.join("real"), dir.join("link")).unwrap();
+
+        let recent = [fs::canonicalize(dir.join("real/a.txt")).unwrap()];
+        let mut finder = Finder::new(&dir.join("link"), &recent);
+        finder.add_files(walk(finder.get_root()));
+        let paths: Vec<&str> = finder.candidates.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["a.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
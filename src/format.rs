@@ -0,0 +1,95 @@
+const BOM: &str = "\u{feff}";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    pub fn toggled(&self) -> Self {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        }
+    }
+}
+
+/// How a file was laid out on disk, written back the same way on save.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub bom: bool,
+}
+
+impl FileFormat {
+    pub fn new() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            bom: false,
+        }
+    }
+
+    /// Format of `contents` and its lines without BOM and line endings.
+    /// Mixed files take the ending of their first line.
+    pub fn detect(contents: &str) -> (Self, Vec<&str>) {
+        let (bom, contents) = match contents.strip_prefix(BOM) {
+            Some(rest) => (true, rest),
+            None => (false, contents),
+        };
+        let line_ending = match contents.find('\n') {
+            Some(idx) if contents[..idx].ends_with('\r') => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        };
+        let final_newline = contents.ends_with('\n');
+        let body = if final_newline {
+            let body = &contents[..contents.len() - 1];
+            body.strip_suffix('\r').unwrap_or(body)
+        } else {
+            contents
+        };
+        let lines = body
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .collect();
+        let format = Self {
+            line_ending,
+            final_newline,
+            bom,
+        };
+        (format, lines)
+    }
+
+    pub fn write<'a>(&self, lines: impl Iterator<Item = &'a str>) -> String {
+        let mut content = String::new();
+        if self.bom {
+            content.push_str(BOM);
+        }
+        for (i, line) in lines.enumerate() {
+            if i > 0 {
+                content.push_str(self.line_ending.as_str());
+            }
+            content.push_str(line);
+        }
+        if self.final_newline {
+            content.push_str(self.line_ending.as_str());
+        }
+        content
+    }
+}
@@ -0,0 +1,40 @@
+/// How well `pattern` matches `candidate` when its characters appear in
+/// order, ignoring case. Runs of matched characters and matches at word
+/// starts score higher, skipped characters cost a little. `None` when the
+/// pattern doesn't match.
+pub fn score(pattern: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut idx = 0;
+    let mut prev_match: Option<usize> = None;
+    for ch in pattern.chars().filter(|ch| !ch.is_whitespace()) {
+        let found = candidate[idx..]
+            .iter()
+            .position(|other| eq_ignore_case(*other, ch))?;
+        let at = idx + found;
+        score += 1;
+        if prev_match.is_some_and(|prev| prev + 1 == at) {
+            score += 8;
+        }
+        if is_word_start(&candidate, at) {
+            score += 6;
+        }
+        // gaps count less the further they are
+        score -= (at - idx).min(8) as i64;
+        prev_match = Some(at);
+        idx = at + 1;
+    }
+    // shorter candidates win ties
+    Some(score * 4 - candidate.len() as i64 / 8)
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+fn is_word_start(chars: &[char], idx: usize) -> bool {
+    let Some(prev) = idx.checked_sub(1).map(|prev| chars[prev]) else {
+        return true;
+    };
+    !prev.is_alphanumeric() || (prev.is_lowercase() && chars[idx].is_uppercase())
+}
@@ -0,0 +1,260 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+struct Pattern {
+    glob: Vec<char>,
+    // `!pattern` takes an earlier match back
+    negated: bool,
+    // `pattern/` only matches directories
+    dir_only: bool,
+    // with a slash it matches from the .gitignore folder, else any file name
+    anchored: bool,
+}
+
+/// Patterns of one `.gitignore` file, relative to its folder.
+pub struct Gitignore {
+    base: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl Gitignore {
+    /// The `.gitignore` of `dir`, `None` when it has none.
+    pub fn load(dir: &Path) -> Option<Self> {
+        Self::from_file(&dir.join(".gitignore"), dir)
+    }
+
+    // patterns of `file`, matched against paths relative to `base`
+    fn from_file(file: &Path, base: &Path) -> Option<Self> {
+        let content = fs::read_to_string(file).ok()?;
+        Some(Self::parse(&content, base))
+    }
+
+    fn parse(content: &str, base: &Path) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            patterns: content.lines().filter_map(Self::parse_line).collect(),
+        }
+    }
+
+    fn parse_line(line: &str) -> Option<Pattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        Some(Pattern {
+            glob: line.chars().collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Whether the last pattern matching `path` ignores it, `None` when no
+    /// pattern matches.
+    fn matches(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.base).ok()?.to_string_lossy();
+        let name = path.file_name()?.to_string_lossy();
+        let relative: Vec<char> = relative.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| {
+                (is_dir || !pattern.dir_only)
+                    && match pattern.anchored {
+                        true => glob(&pattern.glob, &relative),
+                        false => glob(&pattern.glob, &name),
+                    }
+            })
+            .map(|pattern| !pattern.negated)
+    }
+}
+
+/// The ignore files applying to `root` from outside of it: the
+/// `.gitignore` files of its parents up to the repository root, the folder
+/// holding `.git`, and the repository's `.git/info/exclude`. Outermost
+/// first, ready to have `root`'s own `.gitignore` pushed on top. Empty
+/// outside of a repository.
+pub fn ancestors(root: &Path) -> Vec<Gitignore> {
+    let Some(repo) = root.ancestors().find(|dir| dir.join(".git").exists()) else {
+        return vec![];
+    };
+    let mut stack: Vec<Gitignore> = Gitignore::from_file(&repo.join(".git/info/exclude"), repo)
+        .into_iter()
+        .collect();
+    let mut parents: Vec<&Path> = root
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(repo))
+        .collect();
+    parents.reverse();
+    stack.extend(parents.into_iter().filter_map(Gitignore::load));
+    stack
+}
+
+/// Whether `path` is ignored by the stack of `.gitignore` files of its
+/// folders, the deepest one decides first.
+pub fn is_ignored(stack: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    stack
+        .iter()
+        .rev()
+        .find_map(|gitignore| gitignore.matches(path, is_dir))
+        .unwrap_or(false)
+}
+
+// `*` and `?` stay within a path component, `**` crosses them
+fn glob(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=text.len())
+                .filter(|idx| *idx == 0 || text[idx - 1] == '/')
+                .any(|idx| glob(rest, &text[idx..]))
+        }
+        Some('*') => (0..=text.len())
+            .take_while(|idx| *idx == 0 || text[idx - 1] != '/')
+            .any(|idx| glob(&pattern[1..], &text[idx..])),
+        Some('?') => {
+            matches!(text.first(), Some(ch) if *ch != '/') && glob(&pattern[1..], &text[1..])
+        }
+        Some('[') => match (class_end(pattern), text.first()) {
+            (Some(end), Some(ch)) => {
+                in_class(&pattern[1..end], *ch) && glob(&pattern[end + 1..], &text[1..])
+            }
+            (Some(_), None) => false,
+            (None, _) => text.first() == Some(&'[') && glob(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && glob(&pattern[2..], &text[1..])
+        }
+        Some(ch) => text.first() == Some(ch) && glob(&pattern[1..], &text[1..]),
+    }
+}
+
+fn class_end(pattern: &[char]) -> Option<usize> {
+    // a `]` right after the opening bracket is part of the class
+    pattern
+        .iter()
+        .enumerate()
+        .skip(2)
+        .find(|(_, ch)| **ch == ']')
+        .map(|(idx, _)| idx)
+}
+
+fn in_class(class: &[char], ch: char) -> bool {
+    let (negated, class) = match class.first() {
+        Some('!' | '^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut found = false;
+    let mut idx = 0;
+    while idx < class.len() {
+        if idx + 2 < class.len() && class[idx + 1] == '-' {
+            found |= (class[idx]..=class[idx + 2]).contains(&ch);
+            idx += 3;
+        } else {
+            found |= class[idx] == ch;
+            idx += 1;
+        }
+    }
+    found != negated && ch != '/'
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        glob(&pattern, &text)
+    }
+
+    // whether `path`, relative to /repo, is ignored by `content`
+    fn ignored(content: &str, path: &str, is_dir: bool) -> bool {
+        let gitignore = Gitignore::parse(content, Path::new("/repo"));
+        is_ignored(&[gitignore], &Path::new("/repo").join(path), is_dir)
+    }
+
+    #[test]
+    fn stars() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "a/c"));
+        assert!(matches("**/main.rs", "main.rs"));
+        assert!(matches("**/main.rs", "src/bin/main.rs"));
+        assert!(matches("src/**", "src/bin/main.rs"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(!matches("a/**/b", "a/xb"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[abc].txt", "d.txt"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(!matches("file[!0-9]", "file7"));
+        assert!(matches("file[^0-9]", "filex"));
+        assert!(matches("[]]", "]"));
+        // an unclosed bracket is taken literally
+        assert!(matches("[ab", "[ab"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+    }
+
+    #[test]
+    fn negation_and_dirs() {
+        let content = "*.log\n!keep.log\ntarget/\n/build\n# comment\n";
+        assert!(ignored(content, "a.log", false));
+        assert!(ignored(content, "sub/a.log", false));
+        assert!(!ignored(content, "keep.log", false));
+        assert!(ignored(content, "target", true));
+        assert!(ignored(content, "sub/target", true));
+        // `dir/` leaves files of that name alone
+        assert!(!ignored(content, "target", false));
+        // anchored to the folder of the .gitignore
+        assert!(ignored(content, "build", true));
+        assert!(!ignored(content, "sub/build", true));
+    }
+
+    #[test]
+    fn ignore_files_above_the_root() {
+        let repo = env::temp_dir().join(format!("kaelix-gitignore-{}", process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(repo.join(".git/info")).unwrap();
+        fs::create_dir_all(repo.join("sub/deeper")).unwrap();
+        fs::write(repo.join(".gitignore"), "target/\n").unwrap();
+        fs::write(repo.join(".git/info/exclude"), "*.tmp\n").unwrap();
+        fs::write(repo.join("sub/.gitignore"), "!keep.tmp\n").unwrap();
+
+        let root = repo.join("sub/deeper");
+        let stack = ancestors(&root);
+        assert_eq!(stack.len(), 3);
+        assert!(is_ignored(&stack, &root.join("target"), true));
+        assert!(is_ignored(&stack, &root.join("x.tmp"), false));
+        assert!(!is_ignored(&stack, &root.join("keep.tmp"), false));
+        assert!(!is_ignored(&stack, &root.join("main.rs"), false));
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+use std::ops::Range;
+
+use termion::style;
+
+/// What a piece of a rendered line is, decides how it is drawn.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Highlight {
+    LineNumber,
+    Comment,
+    String,
+    Keyword,
+    Type,
+    Number,
+    Constant,
+    Heading,
+    Variable,
+    Match,
+    CurrentMatch,
+    Selection,
+}
+
+impl Highlight {
+    pub const ALL: [Highlight; 12] = [
+        Highlight::LineNumber,
+        Highlight::Comment,
+        Highlight::String,
+        Highlight::Keyword,
+        Highlight::Type,
+        Highlight::Number,
+        Highlight::Constant,
+        Highlight::Heading,
+        Highlight::Variable,
+        Highlight::Match,
+        Highlight::CurrentMatch,
+        Highlight::Selection,
+    ];
+
+    /// Name used for it in the config file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Highlight::LineNumber => "line_number",
+            Highlight::Comment => "comment",
+            Highlight::String => "string",
+            Highlight::Keyword => "keyword",
+            Highlight::Type => "type",
+            Highlight::Number => "number",
+            Highlight::Constant => "constant",
+            Highlight::Heading => "heading",
+            Highlight::Variable => "variable",
+            Highlight::Match => "match",
+            Highlight::CurrentMatch => "current_match",
+            Highlight::Selection => "selection",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|h| h.name() == name)
+    }
+}
+
+/// Byte range of a line drawn with `highlight`. When spans overlap the one
+/// that comes later wins.
+pub struct Span {
+    pub range: Range<usize>,
+    pub highlight: Highlight,
+}
+
+impl Span {
+    pub fn new(range: Range<usize>, highlight: Highlight) -> Self {
+        Self { range, highlight }
+    }
+}
+
+pub fn highlight_at(spans: &[Span], idx: usize) -> Option<Highlight> {
+    spans
+        .iter()
+        .rev()
+        .find(|span| span.range.contains(&idx))
+        .map(|span| span.highlight)
+}
+
+pub fn reset() -> String {
+    style::Reset.to_string()
+}
@@ -0,0 +1,169 @@
+use crate::common::Position;
+
+// Positions stored here are byte positions in the buffer (`col` is a byte
+// index), cursors are the grapheme based `Doc::cursor_pos`.
+
+#[derive(Clone)]
+pub enum Edit {
+    Insert { pos: Position, text: String },
+    Delete { pos: Position, text: String },
+}
+
+impl Edit {
+    /// Position right after `text` once it is in the buffer at `pos`.
+    pub fn end(pos: Position, text: &str) -> Position {
+        match text.rfind('\n') {
+            Some(idx) => Position {
+                row: pos.row + text.matches('\n').count(),
+                col: text.len() - idx - 1,
+            },
+            None => Position {
+                row: pos.row,
+                col: pos.col + text.len(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EditKind {
+    Typing,
+    Deleting,
+    Other,
+}
+
+#[derive(Clone)]
+pub struct Transaction {
+    // changes whenever the transaction does, identifies the buffer state after it
+    id: u64,
+    pub edits: Vec<Edit>,
+    pub cursor_before: Position,
+    pub cursor_after: Position,
+}
+
+pub struct History {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    // kind of the last transaction while it can still take more edits
+    open: Option<EditKind>,
+    // edits between begin_group and end_group make a single undo step
+    grouping: bool,
+    group_started: bool,
+    next_id: u64,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo: vec![],
+            redo: vec![],
+            open: None,
+            grouping: false,
+            group_started: false,
+            next_id: 1,
+        }
+    }
+
+    /// Records an edit that was just applied to the buffer.
+    ///
+    /// Typing and deleting continue the previous step of the same kind when
+    /// they touch where it left off, so a run of keystrokes undoes at once.
+    pub fn record(
+        &mut self,
+        edit: Edit,
+        kind: EditKind,
+        cursor_before: Position,
+        cursor_after: Position,
+    ) {
+        self.redo.clear();
+        let id = self.next_id;
+        self.next_id += 1;
+
+        if self.grouping {
+            if let (true, Some(last)) = (self.group_started, self.undo.last_mut()) {
+                last.id = id;
+                last.edits.push(edit);
+                last.cursor_after = cursor_after;
+                return;
+            }
+            self.group_started = true;
+        } else if self.open == Some(kind) && kind != EditKind::Other {
+            if let Some(last) = self.undo.last_mut() {
+                if Self::continues(last.edits.last(), &edit) {
+                    last.id = id;
+                    last.edits.push(edit);
+                    last.cursor_after = cursor_after;
+                    return;
+                }
+            }
+        }
+
+        self.undo.push(Transaction {
+            id,
+            edits: vec![edit],
+            cursor_before,
+            cursor_after,
+        });
+        self.open = Some(kind);
+    }
+
+    fn continues(prev: Option<&Edit>, edit: &Edit) -> bool {
+        match (prev, edit) {
+            (
+                Some(Edit::Insert {
+                    pos: prev_pos,
+                    text: prev_text,
+                }),
+                Edit::Insert { pos, text },
+            ) => {
+                let end = Edit::end(*prev_pos, prev_text);
+                end.row == pos.row && end.col == pos.col && !text.contains('\n')
+            }
+            (Some(Edit::Delete { pos: prev_pos, .. }), Edit::Delete { pos, text }) => {
+                let end = Edit::end(*pos, text);
+                end.row == prev_pos.row && end.col == prev_pos.col
+            }
+            _ => false,
+        }
+    }
+
+    /// Identifies the current content, equal ids mean equal content.
+    pub fn get_state_id(&self) -> u64 {
+        self.undo.last().map_or(0, |transaction| transaction.id)
+    }
+
+    /// Ends the current group, the next edit starts a new undo step.
+    pub fn seal(&mut self) {
+        self.open = None;
+    }
+
+    /// Everything recorded until `end_group` is undone as one step.
+    pub fn begin_group(&mut self) {
+        self.open = None;
+        self.grouping = true;
+        self.group_started = false;
+    }
+
+    pub fn is_grouping(&self) -> bool {
+        self.grouping
+    }
+
+    pub fn end_group(&mut self) {
+        self.open = None;
+        self.grouping = false;
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Transaction> {
+        self.open = None;
+        let transaction = self.undo.pop()?;
+        self.redo.push(transaction.clone());
+        Some(transaction)
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Transaction> {
+        self.open = None;
+        let transaction = self.redo.pop()?;
+        self.undo.push(transaction.clone());
+        Some(transaction)
+    }
+}
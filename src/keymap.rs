@@ -0,0 +1,643 @@
+use std::collections::HashMap;
+
+use termion::event::Key;
+
+/// Editor commands that can be bound to a key.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Quit,
+    Save,
+    NewDoc,
+    OpenDoc,
+    CloseTab,
+    PrevTab,
+    NextTab,
+    Undo,
+    Redo,
+    Copy,
+    Cut,
+    Paste,
+    CopyToRegister,
+    PasteFromRegister,
+    Find,
+    FindNext,
+    FindPrevious,
+    Replace,
+    ClearSearch,
+    ToggleLineEnding,
+    ToggleFileTree,
+    SwitchFocus,
+    CommandPalette,
+    SplitRight,
+    SplitDown,
+    ClosePane,
+    NextPane,
+    PrevPane,
+    GrowPane,
+    ShrinkPane,
+    TreeUp,
+    TreeDown,
+    TreeFirst,
+    TreeLast,
+    TreeExpand,
+    TreeCollapse,
+    TreeOpen,
+    TreeRefresh,
+    PromptSubmit,
+    PromptCancel,
+    PromptLeft,
+    PromptRight,
+    PromptBackspace,
+    PromptUp,
+    PromptDown,
+    PromptHome,
+    PromptEnd,
+    PromptDeleteWord,
+    PromptComplete,
+}
+
+impl Action {
+    pub const ALL: [Action; 49] = [
+        Action::Quit,
+        Action::Save,
+        Action::NewDoc,
+        Action::OpenDoc,
+        Action::CloseTab,
+        Action::PrevTab,
+        Action::NextTab,
+        Action::Undo,
+        Action::Redo,
+        Action::Copy,
+        Action::Cut,
+        Action::Paste,
+        Action::CopyToRegister,
+        Action::PasteFromRegister,
+        Action::Find,
+        Action::FindNext,
+        Action::FindPrevious,
+        Action::Replace,
+        Action::ClearSearch,
+        Action::ToggleLineEnding,
+        Action::ToggleFileTree,
+        Action::SwitchFocus,
+        Action::CommandPalette,
+        Action::SplitRight,
+        Action::SplitDown,
+        Action::ClosePane,
+        Action::NextPane,
+        Action::PrevPane,
+        Action::GrowPane,
+        Action::ShrinkPane,
+        Action::TreeUp,
+        Action::TreeDown,
+        Action::TreeFirst,
+        Action::TreeLast,
+        Action::TreeExpand,
+        Action::TreeCollapse,
+        Action::TreeOpen,
+        Action::TreeRefresh,
+        Action::PromptSubmit,
+        Action::PromptCancel,
+        Action::PromptLeft,
+        Action::PromptRight,
+        Action::PromptBackspace,
+        Action::PromptUp,
+        Action::PromptDown,
+        Action::PromptHome,
+        Action::PromptEnd,
+        Action::PromptDeleteWord,
+        Action::PromptComplete,
+    ];
+
+    /// Name used for it in the config file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Save => "save",
+            Action::NewDoc => "new_doc",
+            Action::OpenDoc => "open_doc",
+            Action::CloseTab => "close_tab",
+            Action::PrevTab => "prev_tab",
+            Action::NextTab => "next_tab",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Copy => "copy",
+            Action::Cut => "cut",
+            Action::Paste => "paste",
+            Action::CopyToRegister => "copy_to_register",
+            Action::PasteFromRegister => "paste_from_register",
+            Action::Find => "find",
+            Action::FindNext => "find_next",
+            Action::FindPrevious => "find_previous",
+            Action::Replace => "replace",
+            Action::ClearSearch => "clear_search",
+            Action::ToggleLineEnding => "toggle_line_ending",
+            Action::ToggleFileTree => "toggle_file_tree",
+            Action::SwitchFocus => "switch_focus",
+            Action::CommandPalette => "command_palette",
+            Action::SplitRight => "split_right",
+            Action::SplitDown => "split_down",
+            Action::ClosePane => "close_pane",
+            Action::NextPane => "next_pane",
+            Action::PrevPane => "prev_pane",
+            Action::GrowPane => "grow_pane",
+            Action::ShrinkPane => "shrink_pane",
+            Action::TreeUp => "tree_up",
+            Action::TreeDown => "tree_down",
+            Action::TreeFirst => "tree_first",
+            Action::TreeLast => "tree_last",
+            Action::TreeExpand => "tree_expand",
+            Action::TreeCollapse => "tree_collapse",
+            Action::TreeOpen => "tree_open",
+            Action::TreeRefresh => "tree_refresh",
+            Action::PromptSubmit => "prompt_submit",
+            Action::PromptCancel => "prompt_cancel",
+            Action::PromptLeft => "prompt_left",
+            Action::PromptRight => "prompt_right",
+            Action::PromptBackspace => "prompt_backspace",
+            Action::PromptUp => "prompt_up",
+            Action::PromptDown => "prompt_down",
+            Action::PromptHome => "prompt_home",
+            Action::PromptEnd => "prompt_end",
+            Action::PromptDeleteWord => "prompt_delete_word",
+            Action::PromptComplete => "prompt_complete",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    /// Name shown in the command palette.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::Save => "Save",
+            Action::NewDoc => "New document",
+            Action::OpenDoc => "Open document",
+            Action::CloseTab => "Close tab",
+            Action::PrevTab => "Previous tab",
+            Action::NextTab => "Next tab",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::Copy => "Copy",
+            Action::Cut => "Cut",
+            Action::Paste => "Paste",
+            Action::CopyToRegister => "Copy to register",
+            Action::PasteFromRegister => "Paste from register",
+            Action::Find => "Find",
+            Action::FindNext => "Find next",
+            Action::FindPrevious => "Find previous",
+            Action::Replace => "Replace",
+            Action::ClearSearch => "Clear search",
+            Action::ToggleLineEnding => "Toggle line ending",
+            Action::ToggleFileTree => "Toggle file tree",
+            Action::SwitchFocus => "Switch focus",
+            Action::CommandPalette => "Command palette",
+            Action::SplitRight => "Split pane right",
+            Action::SplitDown => "Split pane down",
+            Action::ClosePane => "Close pane",
+            Action::NextPane => "Next pane",
+            Action::PrevPane => "Previous pane",
+            Action::GrowPane => "Grow pane",
+            Action::ShrinkPane => "Shrink pane",
+            Action::TreeUp => "File tree: select previous",
+            Action::TreeDown => "File tree: select next",
+            Action::TreeFirst => "File tree: select first",
+            Action::TreeLast => "File tree: select last",
+            Action::TreeExpand => "File tree: expand",
+            Action::TreeCollapse => "File tree: collapse",
+            Action::TreeOpen => "File tree: open",
+            Action::TreeRefresh => "File tree: refresh",
+            Action::PromptSubmit => "Prompt: submit",
+            Action::PromptCancel => "Prompt: cancel",
+            Action::PromptLeft => "Prompt: cursor left",
+            Action::PromptRight => "Prompt: cursor right",
+            Action::PromptBackspace => "Prompt: delete back",
+            Action::PromptUp => "Prompt: previous entry",
+            Action::PromptDown => "Prompt: next entry",
+            Action::PromptHome => "Prompt: start of input",
+            Action::PromptEnd => "Prompt: end of input",
+            Action::PromptDeleteWord => "Prompt: delete word",
+            Action::PromptComplete => "Prompt: complete path",
+        }
+    }
+
+    /// Where the action makes sense. Global actions work in docs and the
+    /// file tree alike.
+    pub fn context(&self) -> Context {
+        match self {
+            Action::TreeUp
+            | Action::TreeDown
+            | Action::TreeFirst
+            | Action::TreeLast
+            | Action::TreeExpand
+            | Action::TreeCollapse
+            | Action::TreeOpen
+            | Action::TreeRefresh => Context::FileTree,
+            Action::PromptSubmit
+            | Action::PromptCancel
+            | Action::PromptLeft
+            | Action::PromptRight
+            | Action::PromptBackspace
+            | Action::PromptUp
+            | Action::PromptDown
+            | Action::PromptHome
+            | Action::PromptEnd
+            | Action::PromptDeleteWord
+            | Action::PromptComplete => Context::Prompt,
+            _ => Context::Global,
+        }
+    }
+
+    fn default_key(&self) -> Key {
+        match self {
+            Action::Quit => Key::Ctrl('q'),
+            Action::Save => Key::Ctrl('s'),
+            Action::NewDoc => Key::Ctrl('n'),
+            Action::OpenDoc => Key::Ctrl('o'),
+            Action::CloseTab => Key::Ctrl('w'),
+            Action::PrevTab => Key::Ctrl('k'),
+            Action::NextTab => Key::Ctrl('l'),
+            Action::Undo => Key::Ctrl('z'),
+            Action::Redo => Key::Ctrl('y'),
+            Action::Copy => Key::Ctrl('c'),
+            Action::Cut => Key::Ctrl('x'),
+            Action::Paste => Key::Ctrl('v'),
+            Action::CopyToRegister => Key::Alt('c'),
+            Action::PasteFromRegister => Key::Alt('v'),
+            Action::Find => Key::Ctrl('f'),
+            Action::FindNext => Key::Ctrl('g'),
+            Action::FindPrevious => Key::Alt('g'),
+            Action::Replace => Key::Ctrl('r'),
+            Action::ClearSearch => Key::Esc,
+            Action::ToggleLineEnding => Key::Alt('l'),
+            Action::ToggleFileTree => Key::Ctrl('b'),
+            Action::SwitchFocus => Key::Ctrl('e'),
+            Action::CommandPalette => Key::Ctrl('p'),
+            Action::SplitRight => Key::Alt('s'),
+            Action::SplitDown => Key::Alt('S'),
+            Action::ClosePane => Key::Alt('w'),
+            Action::NextPane => Key::Alt('n'),
+            Action::PrevPane => Key::Alt('p'),
+            Action::GrowPane => Key::Alt('='),
+            Action::ShrinkPane => Key::Alt('-'),
+            Action::TreeUp => Key::Up,
+            Action::TreeDown => Key::Down,
+            Action::TreeFirst => Key::Home,
+            Action::TreeLast => Key::End,
+            Action::TreeExpand => Key::Right,
+            Action::TreeCollapse => Key::Left,
+            Action::TreeOpen => Key::Char('\n'),
+            Action::TreeRefresh => Key::Char('r'),
+            Action::PromptSubmit => Key::Char('\n'),
+            Action::PromptCancel => Key::Esc,
+            Action::PromptLeft => Key::Left,
+            Action::PromptRight => Key::Right,
+            Action::PromptBackspace => Key::Backspace,
+            Action::PromptUp => Key::Up,
+            Action::PromptDown => Key::Down,
+            Action::PromptHome => Key::Home,
+            Action::PromptEnd => Key::End,
+            Action::PromptDeleteWord => Key::Ctrl('w'),
+            Action::PromptComplete => Key::Char('\t'),
+        }
+    }
+}
+
+/// The part of the editor a key goes to, each has its own bindings.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Context {
+    // bindings shared by docs and the file tree
+    Global,
+    Doc,
+    FileTree,
+    // the status line while it asks for input
+    Prompt,
+}
+
+impl Context {
+    pub const ALL: [Context; 4] = [
+        Context::Global,
+        Context::Doc,
+        Context::FileTree,
+        Context::Prompt,
+    ];
+
+    /// Name of its table under `[keybindings]` in the config file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Context::Global => "global",
+            Context::Doc => "doc",
+            Context::FileTree => "file_tree",
+            Context::Prompt => "prompt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|context| context.name() == name)
+    }
+
+    // bindings of the context itself come first, then the fallback's
+    fn fallback(&self) -> Option<Context> {
+        match self {
+            Context::Doc | Context::FileTree => Some(Context::Global),
+            Context::Global | Context::Prompt => None,
+        }
+    }
+}
+
+pub enum Lookup {
+    Action(Action),
+    // the keys start a longer sequence, wait for more
+    Prefix,
+    None,
+}
+
+/// Key sequences bound to actions, one map per context.
+pub struct Keymap {
+    maps: HashMap<Context, HashMap<Vec<Key>, Action>>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        let mut maps: HashMap<Context, HashMap<Vec<Key>, Action>> = HashMap::new();
+        for action in Action::ALL {
+            maps.entry(action.context())
+                .or_default()
+                .insert(vec![action.default_key()], action);
+        }
+        Self { maps }
+    }
+
+    /// Replaces the bindings of `action` in `context` with `sequences`.
+    pub fn bind(&mut self, context: Context, action: Action, sequences: Vec<Vec<Key>>) {
+        let map = self.maps.entry(context).or_default();
+        map.retain(|_, bound| *bound != action);
+        for keys in sequences {
+            map.insert(keys, action);
+        }
+    }
+
+    pub fn lookup(&self, context: Context, keys: &[Key]) -> Lookup {
+        let mut context = Some(context);
+        let mut prefix = false;
+        while let Some(current) = context {
+            if let Some(map) = self.maps.get(&current) {
+                if let Some(action) = map.get(keys) {
+                    return Lookup::Action(*action);
+                }
+                prefix |= map.keys().any(|bound| bound.starts_with(keys));
+            }
+            context = current.fallback();
+        }
+        match prefix {
+            true => Lookup::Prefix,
+            false => Lookup::None,
+        }
+    }
+
+    /// Sequences that run `action` in `context`, shortest first.
+    pub fn get_keys(&self, context: Context, action: Action) -> Vec<Vec<Key>> {
+        let mut keys: Vec<Vec<Key>> = Context::ALL
+            .into_iter()
+            .filter_map(|context| self.maps.get(&context))
+            .flat_map(|map| map.iter())
+            .filter(|(_, bound)| **bound == action)
+            .map(|(keys, _)| keys.clone())
+            .filter(|keys| matches!(self.lookup(context, keys), Lookup::Action(found) if found == action))
+            .collect();
+        keys.sort_by_key(|keys| (keys.len(), format_keys(keys)));
+        keys.dedup();
+        keys
+    }
+
+    /// Bindings that can never run because a shorter sequence starting the
+    /// same way is bound too.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = vec![];
+        for context in Context::ALL {
+            let Some(map) = self.maps.get(&context) else {
+                continue;
+            };
+            for (keys, action) in map {
+                for len in 1..keys.len() {
+                    if let Lookup::Action(shorter) = self.lookup(context, &keys[..len]) {
+                        conflicts.push(format!(
+                            "keybindings.{}: `{}` ({}) is never reached, `{}` runs {}",
+                            context.name(),
+                            format_keys(keys),
+                            action.name(),
+                            format_keys(&keys[..len]),
+                            shorter.name()
+                        ));
+                        break;
+                    }
+                }
+            }
+        }
+        conflicts.sort();
+        conflicts
+    }
+}
+
+/// Parses a key sequence of space separated keys, like `ctrl+x ctrl+s`.
+pub fn parse_keys(spec: &str) -> Result<Vec<Key>, String> {
+    let keys = spec
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Result<Vec<Key>, String>>()?;
+    match keys.is_empty() {
+        true => Err("empty key sequence".to_string()),
+        false => Ok(keys),
+    }
+}
+
+/// Inverse of `parse_keys`.
+pub fn format_keys(keys: &[Key]) -> String {
+    keys.iter().map(format_key).collect::<Vec<_>>().join(" ")
+}
+
+fn format_key(key: &Key) -> String {
+    match key {
+        Key::Ctrl(ch) => format!("ctrl+{}", ch),
+        Key::Alt(ch) => format!("alt+{}", ch),
+        Key::Char('\n') => "enter".to_string(),
+        Key::Char('\t') => "tab".to_string(),
+        Key::Char(' ') => "space".to_string(),
+        Key::Char(ch) => ch.to_string(),
+        Key::Esc => "esc".to_string(),
+        Key::Backspace => "backspace".to_string(),
+        Key::Delete => "delete".to_string(),
+        Key::Insert => "insert".to_string(),
+        Key::Left => "left".to_string(),
+        Key::Right => "right".to_string(),
+        Key::Up => "up".to_string(),
+        Key::Down => "down".to_string(),
+        Key::Home => "home".to_string(),
+        Key::End => "end".to_string(),
+        Key::PageUp => "pageup".to_string(),
+        Key::PageDown => "pagedown".to_string(),
+        Key::BackTab => "backtab".to_string(),
+        Key::F(n) => format!("f{}", n),
+        _ => "?".to_string(),
+    }
+}
+
+/// Parses keys written like `ctrl+s`, `alt+g`, `esc`, `f3` or `x`. Names
+/// and modifiers take any case, a single character keeps its own, so
+/// `alt+S` is Alt+Shift+s.
+fn parse_key(spec: &str) -> Result<Key, String> {
+    let spec = spec.trim();
+    let single = |rest: &str| {
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => Ok(ch),
+            _ => Err(format!("invalid key `{}`", spec)),
+        }
+    };
+    let modifier = |prefix: &str| {
+        let head = spec.get(..prefix.len())?;
+        head.eq_ignore_ascii_case(prefix)
+            .then(|| &spec[prefix.len()..])
+    };
+    if let Some(rest) = modifier("ctrl+") {
+        return single(rest).map(Key::Ctrl);
+    }
+    if let Some(rest) = modifier("alt+") {
+        return single(rest).map(Key::Alt);
+    }
+    let lower = spec.to_lowercase();
+    let key = match lower.as_str() {
+        "esc" => Key::Esc,
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "space" => Key::Char(' '),
+        "backtab" => Key::BackTab,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        _ => match lower.strip_prefix('f').map(str::parse::<u8>) {
+            Some(Ok(n)) if (1..=12).contains(&n) => Key::F(n),
+            _ => Key::Char(single(spec)?),
+        },
+    };
+    Ok(key)
+}
+
+/// Arrow key of a Shift+arrow escape sequence. termion doesn't know these
+/// and hands them over as unsupported events.
+pub fn parse_shift_arrow(bytes: &[u8]) -> Option<Key> {
+    let key = match bytes {
+        b"\x1b[1;2A" | b"\x1b[a" => Key::Up,
+        b"\x1b[1;2B" | b"\x1b[b" => Key::Down,
+        b"\x1b[1;2C" | b"\x1b[c" => Key::Right,
+        b"\x1b[1;2D" | b"\x1b[d" => Key::Left,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(spec: &str) -> Vec<Key> {
+        parse_keys(spec).unwrap()
+    }
+
+    #[test]
+    fn lookup_defaults() {
+        let keymap = Keymap::new();
+        assert!(matches!(
+            keymap.lookup(Context::Doc, &keys("ctrl+s")),
+            Lookup::Action(Action::Save)
+        ));
+        assert!(matches!(
+            keymap.lookup(Context::FileTree, &keys("enter")),
+            Lookup::Action(Action::TreeOpen)
+        ));
+        // global bindings don't reach the prompt
+        assert!(matches!(
+            keymap.lookup(Context::Prompt, &keys("ctrl+s")),
+            Lookup::None
+        ));
+    }
+
+    #[test]
+    fn format_parses_back() {
+        for action in Action::ALL {
+            let key = action.default_key();
+            let spec = format_keys(&[key]);
+            assert_eq!(parse_keys(&spec), Ok(vec![key]), "{}", spec);
+        }
+        assert_eq!(keys("ALT+S"), vec![Key::Alt('S')]);
+        assert_eq!(keys("Ctrl+x Enter F3"), keys("ctrl+x enter f3"));
+    }
+
+    #[test]
+    fn lookup_sequences() {
+        let mut keymap = Keymap::new();
+        keymap.bind(
+            Context::Global,
+            Action::Save,
+            vec![keys("ctrl+s"), keys("ctrl+a s")],
+        );
+        assert!(matches!(
+            keymap.lookup(Context::Doc, &keys("ctrl+a")),
+            Lookup::Prefix
+        ));
+        assert!(matches!(
+            keymap.lookup(Context::Doc, &keys("ctrl+a s")),
+            Lookup::Action(Action::Save)
+        ));
+        assert!(matches!(
+            keymap.lookup(Context::Doc, &keys("ctrl+a x")),
+            Lookup::None
+        ));
+        assert!(keymap.conflicts().is_empty());
+    }
+
+    #[test]
+    fn context_bindings_win() {
+        let mut keymap = Keymap::new();
+        keymap.bind(Context::Doc, Action::Find, vec![keys("ctrl+s")]);
+        assert!(matches!(
+            keymap.lookup(Context::Doc, &keys("ctrl+s")),
+            Lookup::Action(Action::Find)
+        ));
+        assert!(matches!(
+            keymap.lookup(Context::FileTree, &keys("ctrl+s")),
+            Lookup::Action(Action::Save)
+        ));
+        assert_eq!(
+            keymap.get_keys(Context::Doc, Action::Save),
+            Vec::<Vec<Key>>::new()
+        );
+    }
+
+    #[test]
+    fn unreachable_sequences() {
+        let mut keymap = Keymap::new();
+        // cut keeps ctrl+x
+        keymap.bind(
+            Context::Global,
+            Action::Save,
+            vec![keys("ctrl+s"), keys("ctrl+x ctrl+s")],
+        );
+        assert_eq!(
+            keymap.conflicts(),
+            ["keybindings.global: `ctrl+x ctrl+s` (save) is never reached, `ctrl+x` runs cut"]
+        );
+        keymap.bind(Context::Global, Action::Cut, vec![keys("alt+x")]);
+        assert!(keymap.conflicts().is_empty());
+    }
+}
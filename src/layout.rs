@@ -0,0 +1,312 @@
+// panes are told apart by ids that are never reused
+pub type PaneId = usize;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    // children side by side, a `│` column between them
+    Horizontal,
+    // children stacked, a `─` row between them
+    Vertical,
+}
+
+/// Size of the first child of a split, the second one gets the rest.
+#[derive(Clone, Copy)]
+pub enum Extent {
+    Cells(usize),
+    Percent(usize),
+}
+
+impl Extent {
+    // the first child keeps at least one cell, and leaves one for the
+    // second, unless there are no cells to share
+    fn resolve(&self, total: usize) -> usize {
+        let cells = match *self {
+            Extent::Cells(cells) => cells.min(total / 2),
+            Extent::Percent(percent) => total * percent / 100,
+        };
+        cells.clamp(1, total.saturating_sub(2).max(1)).min(total)
+    }
+
+    // one step from what is shown in `total` cells, so steps beyond what
+    // `resolve` allows are not stored and undone unseen
+    fn resized(&self, grow: bool, total: usize) -> Self {
+        match *self {
+            Extent::Cells(_) => {
+                let cells = self.resolve(total);
+                match grow {
+                    true => Extent::Cells((cells + 2).min(total / 2).max(cells)),
+                    false => Extent::Cells(cells.saturating_sub(2).max(4)),
+                }
+            }
+            Extent::Percent(percent) => match grow {
+                true => Extent::Percent((percent + 5).min(90)),
+                false => Extent::Percent(percent.saturating_sub(5).max(10)),
+            },
+        }
+    }
+}
+
+/// Part of the screen, counted in cells from the top left, 0 based.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+
+    // the two parts of a split and the separator line between them
+    fn split(&self, axis: Axis, extent: Extent) -> (Rect, Rect) {
+        match axis {
+            Axis::Horizontal => {
+                let first = extent.resolve(self.width);
+                let second = self.width.saturating_sub(first + 1);
+                (
+                    Rect {
+                        width: first,
+                        ..*self
+                    },
+                    Rect {
+                        x: self.x + first + 1,
+                        width: second,
+                        ..*self
+                    },
+                )
+            }
+            Axis::Vertical => {
+                let first = extent.resolve(self.height);
+                let second = self.height.saturating_sub(first + 1);
+                (
+                    Rect {
+                        height: first,
+                        ..*self
+                    },
+                    Rect {
+                        y: self.y + first + 1,
+                        height: second,
+                        ..*self
+                    },
+                )
+            }
+        }
+    }
+}
+
+/// How the screen below the tabs is shared by panes, a binary tree of
+/// splits with panes as leaves.
+pub enum Layout {
+    Pane(PaneId),
+    Split {
+        axis: Axis,
+        extent: Extent,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    /// Rectangle of every pane inside `area`, in order from the top left.
+    pub fn rects(&self, area: Rect) -> Vec<(PaneId, Rect)> {
+        let mut rects = vec![];
+        self.collect_rects(area, &mut rects);
+        rects
+    }
+
+    fn collect_rects(&self, area: Rect, rects: &mut Vec<(PaneId, Rect)>) {
+        match self {
+            Layout::Pane(id) => rects.push((*id, area)),
+            Layout::Split {
+                axis,
+                extent,
+                first,
+                second,
+            } => {
+                let (first_area, second_area) = area.split(*axis, *extent);
+                first.collect_rects(first_area, rects);
+                second.collect_rects(second_area, rects);
+            }
+        }
+    }
+
+    /// Lines of `area` with every pane's lines put in place, `render` gives
+    /// the lines of one pane, each exactly as wide as its rectangle.
+    pub fn render(
+        &self,
+        area: Rect,
+        render: &mut impl FnMut(PaneId, Rect) -> Vec<String>,
+    ) -> Vec<String> {
+        match self {
+            Layout::Pane(id) => render(*id, area),
+            Layout::Split {
+                axis,
+                extent,
+                first,
+                second,
+            } => {
+                let (first_area, second_area) = area.split(*axis, *extent);
+                let first = first.render(first_area, render);
+                let second = second.render(second_area, render);
+                match axis {
+                    Axis::Horizontal => first
+                        .into_iter()
+                        .zip(second)
+                        .map(|(left, right)| format!("{}│{}", left, right))
+                        .collect(),
+                    Axis::Vertical => {
+                        let mut lines = first;
+                        if area.height > lines.len() {
+                            lines.push("─".repeat(area.width));
+                        }
+                        lines.extend(second);
+                        lines
+                    }
+                }
+            }
+        }
+    }
+
+    /// Puts `new` next to `pane`, halving its space.
+    pub fn split(&mut self, pane: PaneId, new: PaneId, axis: Axis) {
+        match self {
+            Layout::Pane(id) if *id == pane => {
+                *self = Layout::Split {
+                    axis,
+                    extent: Extent::Percent(50),
+                    first: Box::new(Layout::Pane(pane)),
+                    second: Box::new(Layout::Pane(new)),
+                };
+            }
+            Layout::Pane(_) => {}
+            Layout::Split { first, second, .. } => {
+                first.split(pane, new, axis);
+                second.split(pane, new, axis);
+            }
+        }
+    }
+
+    /// Puts `new` at the left edge, `cells` wide, like the file tree.
+    pub fn add_left(&mut self, new: PaneId, cells: usize) {
+        let rest = std::mem::replace(self, Layout::Pane(new));
+        *self = Layout::Split {
+            axis: Axis::Horizontal,
+            extent: Extent::Cells(cells),
+            first: Box::new(Layout::Pane(new)),
+            second: Box::new(rest),
+        };
+    }
+
+    /// Takes `pane` out, its sibling gets the space. The last pane stays.
+    pub fn remove(&mut self, pane: PaneId) {
+        let Layout::Split { first, second, .. } = self else {
+            return;
+        };
+        if matches!(**first, Layout::Pane(id) if id == pane) {
+            *self = std::mem::replace(second, Layout::Pane(pane));
+        } else if matches!(**second, Layout::Pane(id) if id == pane) {
+            *self = std::mem::replace(first, Layout::Pane(pane));
+        } else {
+            first.remove(pane);
+            second.remove(pane);
+        }
+    }
+
+    /// Moves the border of the innermost split around `pane` so the pane
+    /// grows or shrinks, the layout filling `area`. Returns whether there
+    /// was a split to resize.
+    pub fn resize(&mut self, pane: PaneId, grow: bool, area: Rect) -> bool {
+        let Layout::Split {
+            axis,
+            extent,
+            first,
+            second,
+        } = self
+        else {
+            return false;
+        };
+        let (first_area, second_area) = area.split(*axis, *extent);
+        if first.resize(pane, grow, first_area) || second.resize(pane, grow, second_area) {
+            return true;
+        }
+        let total = match axis {
+            Axis::Horizontal => area.width,
+            Axis::Vertical => area.height,
+        };
+        if first.contains(pane) {
+            *extent = extent.resized(grow, total);
+            true
+        } else if second.contains(pane) {
+            *extent = extent.resized(!grow, total);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn contains(&self, pane: PaneId) -> bool {
+        match self {
+            Layout::Pane(id) => *id == pane,
+            Layout::Split { first, second, .. } => first.contains(pane) || second.contains(pane),
+        }
+    }
+
+    /// Panes in order from the top left.
+    pub fn panes(&self) -> Vec<PaneId> {
+        match self {
+            Layout::Pane(id) => vec![*id],
+            Layout::Split { first, second, .. } => {
+                let mut panes = first.panes();
+                panes.extend(second.panes());
+                panes
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 40,
+        height: 10,
+    };
+
+    fn width_of(layout: &Layout, pane: PaneId) -> usize {
+        let rects = layout.rects(AREA);
+        rects.iter().find(|(id, _)| *id == pane).unwrap().1.width
+    }
+
+    #[test]
+    fn grow_stops_at_the_limit() {
+        let mut layout = Layout::Pane(0);
+        layout.add_left(1, 10);
+        for _ in 0..20 {
+            assert!(layout.resize(1, true, AREA));
+        }
+        assert_eq!(width_of(&layout, 1), 20);
+        // a single step back shows right away
+        layout.resize(1, false, AREA);
+        assert_eq!(width_of(&layout, 1), 18);
+    }
+
+    #[test]
+    fn resize_the_innermost_split() {
+        let mut layout = Layout::Pane(0);
+        layout.split(0, 1, Axis::Horizontal);
+        layout.split(1, 2, Axis::Vertical);
+        assert!(layout.resize(2, true, AREA));
+        let rects = layout.rects(AREA);
+        assert_eq!(rects[1].1.height, 4);
+        assert_eq!(rects[2].1.height, 5);
+        // the outer split is untouched
+        assert_eq!(width_of(&layout, 0), 20);
+        assert!(!Layout::Pane(0).resize(0, true, AREA));
+    }
+}
@@ -1,15 +1,41 @@
+mod backend;
+mod buffer;
+mod clipboard;
 mod common;
 mod config;
+mod diff;
 mod doc;
 mod editor;
+mod event_loop;
+mod fileio;
 mod filetree;
+mod finder;
+mod format;
+mod fuzzy;
+mod gitignore;
+mod highlight;
+mod history;
+mod keymap;
+mod layout;
+mod palette;
+mod recent;
+mod search;
 mod status_line;
+mod swap;
+mod syntax;
 mod terminal;
+#[cfg(test)]
+mod tests;
+mod text;
+mod theme;
+mod vim;
 
 use std::env::args;
 
+use backend::TermionBackend;
 use config::Config;
 use editor::Editor;
+use terminal::Terminal;
 
 fn main() {
     let args: Vec<String> = args().collect();
@@ -18,7 +44,8 @@ fn main() {
     } else {
         None
     };
-    let config = Config::new();
-    let mut editor = Editor::new(config, file_path);
+    let config = Config::load();
+    let terminal = Terminal::new(Box::new(TermionBackend::new()));
+    let mut editor = Editor::new(config, file_path, terminal);
     editor.run();
 }
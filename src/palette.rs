@@ -0,0 +1,132 @@
+use termion::style;
+
+use crate::{
+    config::Config,
+    fuzzy,
+    keymap::{self, Action, Context},
+    text,
+};
+
+// rows shown at most, the list scrolls past them
+const MAX_ROWS: usize = 10;
+
+struct Entry {
+    action: Action,
+    // keys bound to the action, empty when there are none
+    keys: String,
+}
+
+/// List of editor actions filtered by the status line input.
+pub struct Palette {
+    entries: Vec<Entry>,
+    // indices into entries matching the input, best first
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl Palette {
+    pub fn new(config: &Config) -> Self {
+        let entries: Vec<Entry> = Action::ALL
+            .into_iter()
+            .filter(|action| action.context() == Context::Global)
+            .filter(|action| *action != Action::CommandPalette)
+            .map(|action| Entry {
+                action,
+                keys: config
+                    .keymap
+                    .get_keys(Context::Doc, action)
+                    .first()
+                    .map(|keys| keymap::format_keys(keys))
+                    .unwrap_or_default(),
+            })
+            .collect();
+        Self {
+            matches: (0..entries.len()).collect(),
+            entries,
+            selected: 0,
+        }
+    }
+
+    pub fn filter(&mut self, input: &str) {
+        let mut scored: Vec<(i64, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((fuzzy::score(input, entry.action.title())?, i)))
+            .collect();
+        // stable, so equal scores keep the order of the list
+        scored.sort_by_key(|(score, _)| -score);
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+
+    pub fn select_prev(&mut self) {
+        self.selected = match self.selected {
+            0 => self.matches.len().saturating_sub(1),
+            selected => selected - 1,
+        };
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = match self.selected + 1 < self.matches.len() {
+            true => self.selected + 1,
+            false => 0,
+        };
+    }
+
+    pub fn get_selected(&self) -> Option<Action> {
+        let idx = self.matches.get(self.selected)?;
+        Some(self.entries[*idx].action)
+    }
+
+    /// Rows drawn over the bottom of the screen, at most `height` of them.
+    pub fn render(&self, width: usize, height: usize, config: &Config) -> Vec<String> {
+        if height == 0 {
+            return vec![];
+        }
+        if self.matches.is_empty() {
+            let tab_width = config.general.tab_width;
+            let label = text::sub_cells(
+                " No matching command",
+                0,
+                width,
+                &[],
+                &config.theme,
+                tab_width,
+            );
+            let padding = " ".repeat(width.saturating_sub(text::width(&label, tab_width)));
+            return vec![format!(
+                "{}{}{}{}",
+                style::Faint,
+                label,
+                padding,
+                style::Reset
+            )];
+        }
+        let rows = self.matches.len().min(MAX_ROWS).min(height);
+        let offset = (self.selected + 1).saturating_sub(rows);
+        let tab_width = config.general.tab_width;
+        self.matches
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(rows)
+            .map(|(i, idx)| {
+                let entry = &self.entries[*idx];
+                let keys = format!("{} ", entry.keys);
+                let title_width = width.saturating_sub(text::width(&keys, tab_width));
+                let title = format!(" {}", entry.action.title());
+                let title = text::sub_cells(&title, 0, title_width, &[], &config.theme, tab_width);
+                let padding =
+                    " ".repeat(title_width.saturating_sub(text::width(&title, tab_width)));
+                let prefix = match i == self.selected {
+                    true => style::Invert.to_string(),
+                    false => String::new(),
+                };
+                let row = format!("{}{}{}", title, padding, keys);
+                let row = text::sub_cells(&row, 0, width, &[], &config.theme, tab_width);
+                format!("{}{}{}", prefix, row, style::Reset)
+            })
+            .collect()
+    }
+}
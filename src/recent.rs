@@ -0,0 +1,55 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const MAX_RECENT: usize = 50;
+
+/// Files opened lately, newest first, kept in the state dir between
+/// sessions.
+pub struct RecentFiles {
+    paths: Vec<PathBuf>,
+    // none when there is no state dir, the list is then only kept in memory
+    file: Option<PathBuf>,
+}
+
+impl RecentFiles {
+    pub fn load(state_dir: Option<&Path>) -> Self {
+        let file = state_dir.map(|dir| dir.join("recent_files"));
+        let paths = file
+            .as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|content| content.lines().map(PathBuf::from).collect())
+            .unwrap_or_default();
+        Self { paths, file }
+    }
+
+    pub fn get_paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Moves `path` to the front and saves the list, failing quietly as
+    /// the list is only a convenience.
+    pub fn add(&mut self, path: &str) {
+        let Ok(path) = fs::canonicalize(path) else {
+            return;
+        };
+        self.paths.retain(|recent| *recent != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(MAX_RECENT);
+        if let Some(file) = &self.file {
+            let _ = Self::save(file, &self.paths);
+        }
+    }
+
+    fn save(file: &Path, paths: &[PathBuf]) -> std::io::Result<()> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content: String = paths
+            .iter()
+            .map(|path| format!("{}\n", path.display()))
+            .collect();
+        fs::write(file, content)
+    }
+}
@@ -0,0 +1,175 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{buffer::Buffer, common::Position};
+
+// Matches never span lines. Positions are byte positions in the buffer.
+
+pub struct Match {
+    pub row: usize,
+    pub range: Range<usize>,
+}
+
+impl Match {
+    pub fn start(&self) -> Position {
+        Position {
+            row: self.row,
+            col: self.range.start,
+        }
+    }
+}
+
+pub struct Search {
+    regex: Regex,
+}
+
+impl Search {
+    /// Builds a search from user input.
+    ///
+    /// Input that is not a valid regex is searched for literally, so typing
+    /// `foo(` doesn't lose the matches while the expression is incomplete.
+    /// Lower case input matches case insensitively.
+    pub fn new(pattern: &str) -> Option<Self> {
+        if pattern.is_empty() {
+            return None;
+        }
+        let case_insensitive = !pattern.chars().any(char::is_uppercase);
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .or_else(|_| {
+                RegexBuilder::new(&regex::escape(pattern))
+                    .case_insensitive(case_insensitive)
+                    .build()
+            })
+            .ok()?;
+        Some(Self { regex })
+    }
+
+    /// Search for a strict regex, errors are reported back to the user.
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            regex: Regex::new(pattern)?,
+        })
+    }
+
+    pub fn get_regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    pub fn matches_in_line(&self, line: &str) -> Vec<Range<usize>> {
+        self.regex
+            .find_iter(line)
+            .filter(|m| !m.is_empty())
+            .map(|m| m.range())
+            .collect()
+    }
+
+    /// First match starting at or after `from`, wrapping around the end of
+    /// the buffer.
+    pub fn find_forward(&self, buffer: &Buffer, from: Position) -> Option<Match> {
+        for (i, line) in buffer.lines_from(from.row).enumerate() {
+            let row = from.row + i;
+            let min_col = if i == 0 { from.col } else { 0 };
+            if let Some(range) = self
+                .matches_in_line(line)
+                .into_iter()
+                .find(|range| range.start >= min_col)
+            {
+                return Some(Match { row, range });
+            }
+        }
+        for (row, line) in buffer.lines().enumerate().take(from.row + 1) {
+            if let Some(range) = self
+                .matches_in_line(line)
+                .into_iter()
+                .find(|range| row < from.row || range.start < from.col)
+            {
+                return Some(Match { row, range });
+            }
+        }
+        None
+    }
+
+    /// Last match starting before `from`, wrapping around the start of the
+    /// buffer.
+    pub fn find_backward(&self, buffer: &Buffer, from: Position) -> Option<Match> {
+        let len = buffer.len_lines();
+        let rows = (0..=from.row).rev().chain((from.row..len).rev());
+        for (i, row) in rows.enumerate() {
+            let line = buffer.line(row);
+            let found = self
+                .matches_in_line(line)
+                .into_iter()
+                .rev()
+                .find(|range| i > 0 || range.start < from.col);
+            if let Some(range) = found {
+                return Some(Match { row, range });
+            }
+        }
+        None
+    }
+}
+
+/// State of a find and replace going through the document match by match.
+pub struct Replace {
+    pub replacement: String,
+    // where the replace started, it ends there after wrapping around
+    stop: Position,
+    wrapped: bool,
+    pub count: usize,
+}
+
+impl Replace {
+    pub fn new(replacement: String, stop: Position) -> Self {
+        Self {
+            replacement,
+            stop,
+            wrapped: false,
+            count: 0,
+        }
+    }
+
+    /// Next match to offer from `from`, `None` once the whole document was
+    /// gone through.
+    pub fn next_match(
+        &mut self,
+        search: &Search,
+        buffer: &Buffer,
+        from: Position,
+    ) -> Option<Match> {
+        let found = search.find_forward(buffer, from)?;
+        if (found.row, found.range.start) < (from.row, from.col) {
+            if self.wrapped {
+                return None;
+            }
+            self.wrapped = true;
+        }
+        if self.wrapped && (found.row, found.range.start) >= (self.stop.row, self.stop.col) {
+            return None;
+        }
+        Some(found)
+    }
+
+    /// Text replacing `found`, with capture group references expanded.
+    pub fn expand(&self, search: &Search, buffer: &Buffer, found: &Match) -> String {
+        let mut expanded = String::new();
+        if let Some(caps) = search
+            .get_regex()
+            .captures_at(buffer.line(found.row), found.range.start)
+        {
+            caps.expand(&self.replacement, &mut expanded);
+        }
+        expanded
+    }
+
+    /// Counts a replacement of `found` by `new_len` bytes and keeps the stop
+    /// position in place when the line got shorter or longer before it.
+    pub fn record_replacement(&mut self, found: &Match, new_len: usize) {
+        if self.wrapped && found.row == self.stop.row && found.range.start < self.stop.col {
+            self.stop.col = (self.stop.col + new_len).saturating_sub(found.range.len());
+        }
+        self.count += 1;
+    }
+}
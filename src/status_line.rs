@@ -1,38 +1,70 @@
-use termion::event::Key;
+use std::{collections::HashMap, env, fs, mem, path::PathBuf};
+
+use termion::{event::Key, style};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{config::Config, keymap::Action, text};
 
 #[derive(PartialEq)]
 enum Status {
     Input(String, String),
+    // waits for a single key answering the prompt
+    Choice(String),
     Still(String),
 }
 
 impl Status {
     pub fn insert(&mut self, idx: usize, ch: char) {
         match self {
-            Status::Input(_, text) => {
-                text.insert(idx, ch);
+            Status::Input(_, input) => {
+                input.insert(text::byte_index(input, idx), ch);
+            }
+            Status::Choice(_) | Status::Still(_) => {
+                unimplemented!("command line is still, can't push_char")
             }
-            Status::Still(_) => unimplemented!("command line is still, can't push_char"),
         }
     }
 
     fn handle_backspace(&mut self, idx: usize) {
         match self {
-            Status::Input(_, text) => {
-                text.remove(idx);
+            Status::Input(_, input) => {
+                let start = text::byte_index(input, idx);
+                let end = text::byte_index(input, idx + 1);
+                input.replace_range(start..end, "");
+            }
+            Status::Choice(_) | Status::Still(_) => {
+                unimplemented!("command line is still, can't backspace")
             }
-            Status::Still(_) => unimplemented!("command line is still, can't backspace"),
         }
     }
 }
 
+// entries kept per prompt
+const MAX_HISTORY: usize = 100;
+// rows the completion candidates take at most
+const MAX_COMPLETION_ROWS: usize = 5;
+
 pub struct StatusLine {
     status: Status,
     cursor: usize,
+    // whether Tab completes the input as a file path
+    completes_paths: bool,
+    // file names the input can be completed with, shown above the prompt
+    completions: Vec<String>,
+    // the input without the file name being completed
+    completion_base: String,
+    completion_idx: Option<usize>,
+    // answers given so far, per prompt, oldest first
+    history: HashMap<String, Vec<String>>,
+    history_idx: Option<usize>,
+    // the input typed before browsing the history
+    draft: String,
 }
 
 pub enum InputStatus {
     Processing,
+    // the input text changed, carries the new text
+    Changed(String),
     Cancelled,
     Done(String),
 }
@@ -42,52 +74,180 @@ impl StatusLine {
         Self {
             status: Status::Still("This is command line".to_string()),
             cursor: 0,
+            completes_paths: false,
+            completions: vec![],
+            completion_base: String::new(),
+            completion_idx: None,
+            history: HashMap::new(),
+            history_idx: None,
+            draft: String::new(),
         }
     }
     pub fn is_taking_input(&self) -> bool {
-        matches!(self.status, Status::Input(_, _))
+        matches!(self.status, Status::Input(_, _) | Status::Choice(_))
     }
     pub fn set_status(&mut self, text: String) {
         self.status = Status::Still(text);
     }
-    pub fn render(&self) -> String {
-        match &self.status {
+    /// The status line, with tabs expanded and control characters
+    /// replaced like in docs.
+    pub fn render(&self, config: &Config) -> String {
+        let line = match &self.status {
             Status::Input(prefix, input) => format!("{}: {}", prefix, input),
+            Status::Choice(prefix) => format!("{}: ", prefix),
             Status::Still(mes) => mes.to_string(),
+        };
+        let tab_width = config.general.tab_width;
+        let width = text::width(&line, tab_width);
+        text::sub_cells(&line, 0, width, &[], &config.theme, tab_width)
+    }
+
+    /// Rows listing the completion candidates, drawn right above the prompt.
+    pub fn render_completions(&self, width: usize, config: &Config) -> Vec<String> {
+        let tab_width = config.general.tab_width;
+        let mut rows: Vec<String> = vec![];
+        let mut row = String::new();
+        let mut row_width = 0;
+        // the rows up to the selected candidate stay visible
+        let mut selected_row = 0;
+        for (i, name) in self.completions.iter().enumerate() {
+            let name_width = (text::width(name, tab_width) + 2).min(width);
+            if row_width > 0 && row_width + name_width > width {
+                rows.push(format!("{}{}", row, " ".repeat(width - row_width)));
+                row.clear();
+                row_width = 0;
+            }
+            let name = text::sub_cells(
+                name,
+                0,
+                width.saturating_sub(2),
+                &[],
+                &config.theme,
+                tab_width,
+            );
+            if self.completion_idx == Some(i) {
+                row.push_str(&format!(" {}{}{} ", style::Invert, name, style::Reset));
+                selected_row = rows.len();
+            } else {
+                row.push_str(&format!(" {} ", name));
+            }
+            row_width += name_width;
         }
+        if row_width > 0 {
+            rows.push(format!("{}{}", row, " ".repeat(width - row_width)));
+        }
+        let start = (selected_row + 1).saturating_sub(MAX_COMPLETION_ROWS);
+        rows.into_iter()
+            .skip(start)
+            .take(MAX_COMPLETION_ROWS)
+            .collect()
     }
-    pub fn process_key(&mut self, key: &Key) -> InputStatus {
-        match key {
-            Key::Esc => InputStatus::Cancelled,
-            Key::Char('\n') => match &self.status {
-                Status::Input(_, input) => InputStatus::Done(input.to_string()),
-                Status::Still(_) => unimplemented!(),
+
+    pub fn process_action(&mut self, action: Action) -> InputStatus {
+        if let Status::Choice(_) = self.status {
+            return match action {
+                Action::PromptCancel => InputStatus::Cancelled,
+                _ => InputStatus::Processing,
+            };
+        }
+        if action != Action::PromptComplete {
+            self.completions.clear();
+            self.completion_idx = None;
+        }
+        match action {
+            Action::PromptCancel => InputStatus::Cancelled,
+            Action::PromptSubmit => match &self.status {
+                Status::Input(prefix, input) => {
+                    let history = self.history.entry(prefix.to_string()).or_default();
+                    if !input.is_empty() && history.last() != Some(input) {
+                        history.push(input.to_string());
+                        if history.len() > MAX_HISTORY {
+                            history.remove(0);
+                        }
+                    }
+                    // kept as typed in the history, opened with `~` expanded
+                    match self.completes_paths {
+                        true => InputStatus::Done(expand_home(input)),
+                        false => InputStatus::Done(input.to_string()),
+                    }
+                }
+                Status::Choice(_) | Status::Still(_) => unimplemented!(),
             },
-            Key::Char(ch) => self.write_input(ch.to_owned()),
-            Key::Backspace => self.handle_backspace(),
-            Key::Left => {
+            Action::PromptBackspace => self.handle_backspace(),
+            Action::PromptDeleteWord => self.delete_word(),
+            Action::PromptLeft => {
                 self.cursor = self.cursor.saturating_sub(1);
                 InputStatus::Processing
             }
-            Key::Right => {
-                self.cursor = self.cursor.saturating_add(1);
+            Action::PromptRight => {
+                self.cursor = (self.cursor + 1).min(self.input_len());
+                InputStatus::Processing
+            }
+            Action::PromptHome => {
+                self.cursor = 0;
+                InputStatus::Processing
+            }
+            Action::PromptEnd => {
+                self.cursor = self.input_len();
                 InputStatus::Processing
             }
+            Action::PromptUp => self.history_prev(),
+            Action::PromptDown => self.history_next(),
+            Action::PromptComplete if self.completes_paths => self.complete(),
+            _ => InputStatus::Processing,
+        }
+    }
+
+    /// Keys not bound to a prompt action, typed characters answer a choice
+    /// or go into the input.
+    pub fn process_key(&mut self, key: &Key) -> InputStatus {
+        self.completions.clear();
+        self.completion_idx = None;
+        match (&self.status, key) {
+            (Status::Choice(_), Key::Char(ch)) => InputStatus::Done(ch.to_string()),
+            (Status::Input(_, _), Key::Char(ch)) => self.write_input(ch.to_owned()),
             _ => InputStatus::Processing,
         }
     }
 
-    pub fn get_cursor_with_prefix(&self) -> usize {
+    pub fn get_cursor_with_prefix(&self, tab_width: usize) -> usize {
         match &self.status {
-            Status::Input(p, _) => (p.len() + 2) + (self.cursor + 1),
+            Status::Input(p, input) => {
+                (text::width(p, tab_width) + 2)
+                    + (text::cells_before(input, self.cursor, tab_width) + 1)
+            }
+            Status::Choice(p) => text::width(p, tab_width) + 3,
             Status::Still(_) => unimplemented!(),
         }
     }
 
+    fn input_len(&self) -> usize {
+        match &self.status {
+            Status::Input(_, input) => text::grapheme_count(input),
+            Status::Choice(_) | Status::Still(_) => 0,
+        }
+    }
+
+    // replaces the whole input, the cursor goes to its end
+    fn set_input(&mut self, text: String) -> InputStatus {
+        if let Status::Input(_, input) = &mut self.status {
+            *input = text;
+        }
+        self.cursor = self.input_len();
+        self.changed()
+    }
+
     fn write_input(&mut self, ch: char) -> InputStatus {
         self.status.insert(self.cursor, ch);
         self.cursor += 1;
-        InputStatus::Processing
+        self.changed()
+    }
+
+    fn changed(&self) -> InputStatus {
+        match &self.status {
+            Status::Input(_, input) => InputStatus::Changed(input.to_string()),
+            _ => InputStatus::Processing,
+        }
     }
 
     fn handle_backspace(&mut self) -> InputStatus {
@@ -96,15 +256,198 @@ impl StatusLine {
                 if !txt.is_empty() && self.cursor > 0 {
                     self.status.handle_backspace(self.cursor - 1);
                     self.cursor -= 1;
+                    return self.changed();
                 }
             }
-            Status::Still(_) => {}
+            Status::Choice(_) | Status::Still(_) => {}
         }
         InputStatus::Processing
     }
 
-    pub(crate) fn take_input(&mut self, prefix: String) {
+    // deletes back over spaces, then over one word or run of punctuation
+    fn delete_word(&mut self) -> InputStatus {
+        let Status::Input(_, input) = &mut self.status else {
+            return InputStatus::Processing;
+        };
+        let end = text::byte_index(input, self.cursor);
+        let graphemes: Vec<&str> = input[..end].graphemes(true).collect();
+        let mut start = graphemes.len();
+        while start > 0 && text::char_class(graphemes[start - 1]) == 1 {
+            start -= 1;
+        }
+        if let Some(class) = start
+            .checked_sub(1)
+            .map(|idx| text::char_class(graphemes[idx]))
+        {
+            while start > 0 && text::char_class(graphemes[start - 1]) == class {
+                start -= 1;
+            }
+        }
+        if start == graphemes.len() {
+            return InputStatus::Processing;
+        }
+        let start_byte = text::byte_index(input, start);
+        input.replace_range(start_byte..end, "");
+        self.cursor = start;
+        self.changed()
+    }
+
+    fn history_prev(&mut self) -> InputStatus {
+        let Status::Input(prefix, input) = &self.status else {
+            return InputStatus::Processing;
+        };
+        let Some(history) = self
+            .history
+            .get(prefix)
+            .filter(|history| !history.is_empty())
+        else {
+            return InputStatus::Processing;
+        };
+        let idx = match self.history_idx {
+            Some(idx) => idx.saturating_sub(1),
+            None => {
+                self.draft = input.to_string();
+                history.len() - 1
+            }
+        };
+        self.history_idx = Some(idx);
+        let entry = history[idx].to_string();
+        self.set_input(entry)
+    }
+
+    fn history_next(&mut self) -> InputStatus {
+        let (Status::Input(prefix, _), Some(idx)) = (&self.status, self.history_idx) else {
+            return InputStatus::Processing;
+        };
+        let history = &self.history[prefix];
+        let entry = match idx + 1 < history.len() {
+            true => {
+                self.history_idx = Some(idx + 1);
+                history[idx + 1].to_string()
+            }
+            false => {
+                self.history_idx = None;
+                mem::take(&mut self.draft)
+            }
+        };
+        self.set_input(entry)
+    }
+
+    // first Tab completes as far as the candidates agree, the next ones
+    // go through them
+    fn complete(&mut self) -> InputStatus {
+        if !self.completions.is_empty() {
+            let idx = self
+                .completion_idx
+                .map_or(0, |idx| (idx + 1) % self.completions.len());
+            self.completion_idx = Some(idx);
+            let completed = format!("{}{}", self.completion_base, self.completions[idx]);
+            return self.set_input(completed);
+        }
+        let Status::Input(_, input) = &self.status else {
+            return InputStatus::Processing;
+        };
+        let (base, partial) = match input.rfind('/') {
+            Some(idx) => input.split_at(idx + 1),
+            None => ("", input.as_str()),
+        };
+        let mut names = path_candidates(base, partial);
+        match names.len() {
+            0 => InputStatus::Processing,
+            1 => {
+                let completed = format!("{}{}", base, names[0]);
+                self.set_input(completed)
+            }
+            _ => {
+                names.sort();
+                let common = common_prefix(&names);
+                self.completion_base = base.to_string();
+                let completed = format!("{}{}", base, common);
+                self.completions = names;
+                self.set_input(completed)
+            }
+        }
+    }
+
+    pub fn completes_paths(&self) -> bool {
+        matches!(self.status, Status::Input(_, _)) && self.completes_paths
+    }
+
+    pub(crate) fn take_input(&mut self, prefix: String, completes_paths: bool) {
         self.status = Status::Input(prefix, String::new());
         self.cursor = 0;
+        self.completes_paths = completes_paths;
+        self.completions.clear();
+        self.completion_idx = None;
+        self.history_idx = None;
+    }
+
+    pub(crate) fn take_choice(&mut self, prefix: String) {
+        self.status = Status::Choice(prefix);
+        self.cursor = 0;
+    }
+}
+
+/// `path` with a leading `~` or `~/` standing for the home folder replaced
+/// by it, like a shell does.
+fn expand_home(path: &str) -> String {
+    let Some(home) = env::var_os("HOME").map(PathBuf::from) else {
+        return path.to_string();
+    };
+    match path.strip_prefix("~/") {
+        Some(rest) => home.join(rest).display().to_string(),
+        None if path == "~" => home.display().to_string(),
+        None => path.to_string(),
+    }
+}
+
+// names in folder `base` starting with `partial`, folders end with a slash
+fn path_candidates(base: &str, partial: &str) -> Vec<String> {
+    let dir = match base {
+        "" => PathBuf::from("."),
+        _ => PathBuf::from(expand_home(base)),
+    };
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    read_dir
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // hidden files only when asked for
+            if !name.starts_with(partial) || (name.starts_with('.') && !partial.starts_with('.')) {
+                return None;
+            }
+            match entry.path().is_dir() {
+                true => Some(format!("{}/", name)),
+                false => Some(name),
+            }
+        })
+        .collect()
+}
+
+fn common_prefix(names: &[String]) -> &str {
+    let first = &names[0];
+    let len = names[1..].iter().fold(first.len(), |len, name| {
+        first[..len]
+            .char_indices()
+            .zip(name.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(name.len()), |((idx, _), _)| idx)
+    });
+    &first[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn home_is_expanded() {
+        let home = env::var("HOME").unwrap();
+        assert_eq!(expand_home("~/notes.txt"), format!("{}/notes.txt", home));
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~user/a"), "~user/a");
+        assert_eq!(expand_home("a/~/b"), "a/~/b");
     }
 }
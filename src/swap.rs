@@ -0,0 +1,181 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// first line of every swap file, anything else in the folder is left alone
+const HEADER: &str = "kaelix swap";
+const UNTITLED_PREFIX: &str = "untitled-";
+
+// a file's swap file, then the names tried while another editor owns it
+const EXTENSIONS: [&str; 6] = ["swp", "swo", "swn", "swm", "swl", "swk"];
+
+// numbers the untitled docs of this process
+static NEXT_UNTITLED: AtomicUsize = AtomicUsize::new(0);
+
+/// The unsaved content of a doc, written by an editor that is no longer
+/// running.
+pub struct Leftover {
+    pub file: PathBuf,
+    pub content: String,
+}
+
+/// The folder of the swap files, in the state dir.
+pub fn dir(state_dir: &Path) -> PathBuf {
+    state_dir.join("swap")
+}
+
+// the names the swap file of `path` can have in `dir`, after the absolute
+// path with `%` for every `/`, like vim does
+fn candidates(dir: &Path, path: &str) -> Vec<PathBuf> {
+    let Some(path) = absolute(path) else {
+        return vec![];
+    };
+    let name = path.display().to_string().replace('/', "%");
+    EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(format!("{}.{}", name, extension)))
+        .collect()
+}
+
+/// Swap file this process can write for the doc saved at `path`: the
+/// first name no other editor, running or dead, owns. `None` when all are
+/// taken.
+pub fn file_for(dir: &Path, path: &str) -> Option<PathBuf> {
+    candidates(dir, path)
+        .into_iter()
+        .find(|file| !file.exists() || owner(file) == Some(process::id()))
+}
+
+/// Whether `file` is one of the names of the swap file of `path`.
+pub fn is_file_for(dir: &Path, file: &Path, path: &str) -> bool {
+    candidates(dir, path)
+        .iter()
+        .any(|candidate| candidate == file)
+}
+
+/// Id of another editor still running with `path` open.
+pub fn running_owner(dir: &Path, path: &str) -> Option<u32> {
+    candidates(dir, path)
+        .iter()
+        .filter_map(|file| owner(file))
+        .find(|pid| *pid != process::id() && is_running(*pid))
+}
+
+/// A swap file for an untitled doc, a new one on every call.
+pub fn untitled_file(dir: &Path) -> PathBuf {
+    let id = NEXT_UNTITLED.fetch_add(1, Ordering::Relaxed);
+    dir.join(format!("{}{}-{}.swp", UNTITLED_PREFIX, process::id(), id))
+}
+
+// the file need not exist yet, a doc can be given a new path
+fn absolute(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if let Ok(path) = fs::canonicalize(path) {
+        return Some(path);
+    }
+    let name = path.file_name()?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => env::current_dir().ok()?,
+    };
+    let parent = fs::canonicalize(&parent).unwrap_or(parent);
+    Some(parent.join(name))
+}
+
+/// Writes `content` to `file`, stating this process as its owner.
+pub fn write(file: &Path, path: Option<&str>, content: &str) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let swap = format!(
+        "{}\npid {}\npath {}\n\n{}",
+        HEADER,
+        process::id(),
+        path.unwrap_or_default(),
+        content
+    );
+    // a crash while writing leaves the previous swap file whole
+    let mut temp = file.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, swap)?;
+    fs::rename(&temp, file)
+}
+
+pub fn remove(file: &Path) {
+    let _ = fs::remove_file(file);
+}
+
+/// The first swap file left for the doc at `path` by an editor that died.
+pub fn leftover_for(dir: &Path, path: &str) -> Option<Leftover> {
+    candidates(dir, path)
+        .iter()
+        .find_map(|file| read_leftover(file))
+}
+
+/// Swap files of untitled docs left by editors that died.
+pub fn leftover_untitled(dir: &Path) -> Vec<Leftover> {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut files: Vec<PathBuf> = read_dir
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(UNTITLED_PREFIX)
+        })
+        .map(|entry| entry.path())
+        // not the temp files of a write cut short
+        .filter(|file| file.extension().is_some_and(|extension| extension == "swp"))
+        .collect();
+    files.sort();
+    files
+        .iter()
+        .filter_map(|file| read_leftover(file))
+        .collect()
+}
+
+// pid and content, None for files that are no swap files
+fn read(file: &Path) -> Option<(u32, String)> {
+    let swap = fs::read_to_string(file).ok()?;
+    let (header, content) = swap.split_once("\n\n")?;
+    let mut lines = header.lines();
+    if lines.next()? != HEADER {
+        return None;
+    }
+    let pid = lines.next()?.strip_prefix("pid ")?.parse().ok()?;
+    Some((pid, content.to_string()))
+}
+
+fn owner(file: &Path) -> Option<u32> {
+    read(file).map(|(pid, _)| pid)
+}
+
+// None for files that are no swap files or belong to a running editor
+fn read_leftover(file: &Path) -> Option<Leftover> {
+    let (pid, content) = read(file)?;
+    if is_running(pid) {
+        return None;
+    }
+    Some(Leftover {
+        file: file.to_path_buf(),
+        content,
+    })
+}
+
+fn is_running(pid: u32) -> bool {
+    if pid == process::id() {
+        return true;
+    }
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 sends nothing, it only checks that the process exists
+    let result = unsafe { libc::kill(pid, 0) };
+    // a process of another user can't be signalled but is running
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
@@ -0,0 +1,379 @@
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::{
+    buffer::Buffer,
+    highlight::{Highlight, Span},
+};
+
+// Languages are plain data, adding one means adding an entry to SYNTAXES.
+
+pub struct Delimited {
+    pub open: &'static str,
+    pub close: &'static str,
+    pub escape: Option<char>,
+    // whether the token continues on the next line when `close` is missing
+    pub multiline: bool,
+    pub highlight: Highlight,
+}
+
+pub struct Pattern {
+    pub regex: &'static str,
+    pub highlight: Highlight,
+    // only tried at the first non blank character of a line
+    pub line_start: bool,
+}
+
+pub struct Syntax {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    pub file_names: &'static [&'static str],
+    pub line_comments: &'static [&'static str],
+    // block comments and strings, tried in order
+    pub delimited: &'static [Delimited],
+    // tried before everything else, a capture group limits the highlight to it
+    pub patterns: &'static [Pattern],
+    pub keywords: &'static [&'static str],
+    pub types: &'static [&'static str],
+    pub constants: &'static [&'static str],
+    pub numbers: bool,
+}
+
+const fn delimited(
+    open: &'static str,
+    close: &'static str,
+    escape: Option<char>,
+    multiline: bool,
+    highlight: Highlight,
+) -> Delimited {
+    Delimited {
+        open,
+        close,
+        escape,
+        multiline,
+        highlight,
+    }
+}
+
+const fn pattern(regex: &'static str, highlight: Highlight, line_start: bool) -> Pattern {
+    Pattern {
+        regex,
+        highlight,
+        line_start,
+    }
+}
+
+pub static SYNTAXES: &[Syntax] = &[
+    Syntax {
+        name: "Rust",
+        extensions: &["rs"],
+        file_names: &[],
+        line_comments: &["//"],
+        delimited: &[
+            delimited("/*", "*/", None, true, Highlight::Comment),
+            delimited("r#\"", "\"#", None, true, Highlight::String),
+            delimited("b\"", "\"", Some('\\'), true, Highlight::String),
+            delimited("\"", "\"", Some('\\'), true, Highlight::String),
+        ],
+        patterns: &[
+            pattern(
+                r"'(?:[^'\\]|\\.|\\u\{[0-9a-fA-F]+\})'",
+                Highlight::String,
+                false,
+            ),
+            pattern(r"'[A-Za-z_]\w*", Highlight::Type, false),
+            pattern(r"#!?\[[^\]]*\]", Highlight::Constant, false),
+            pattern(r"([A-Za-z_]\w*)!", Highlight::Keyword, false),
+        ],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+            "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "type",
+            "unsafe", "use", "where", "while",
+        ],
+        types: &[
+            "Self", "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16",
+            "i32", "i64", "i128", "isize", "f32", "f64", "String", "Vec", "Option", "Result",
+            "Box",
+        ],
+        constants: &["true", "false", "None", "Some", "Ok", "Err"],
+        numbers: true,
+    },
+    Syntax {
+        name: "TOML",
+        extensions: &["toml"],
+        file_names: &["Cargo.lock"],
+        line_comments: &["#"],
+        delimited: &[
+            delimited("\"\"\"", "\"\"\"", Some('\\'), true, Highlight::String),
+            delimited("'''", "'''", None, true, Highlight::String),
+            delimited("\"", "\"", Some('\\'), false, Highlight::String),
+            delimited("'", "'", None, false, Highlight::String),
+        ],
+        patterns: &[
+            pattern(r"\[\[?[^\]]*\]\]?", Highlight::Heading, true),
+            pattern(r#"([A-Za-z0-9_.\-"]+)\s*="#, Highlight::Variable, true),
+        ],
+        keywords: &[],
+        types: &[],
+        constants: &["true", "false", "inf", "nan"],
+        numbers: true,
+    },
+    Syntax {
+        name: "Markdown",
+        extensions: &["md", "markdown"],
+        file_names: &[],
+        line_comments: &[],
+        delimited: &[
+            delimited("```", "```", None, true, Highlight::String),
+            delimited("<!--", "-->", None, true, Highlight::Comment),
+            delimited("`", "`", None, false, Highlight::String),
+        ],
+        patterns: &[
+            pattern(r"#{1,6}\s.*", Highlight::Heading, true),
+            pattern(r"(?:[-*+]|\d+\.)\s", Highlight::Keyword, true),
+            pattern(r">.*", Highlight::Comment, true),
+            pattern(r"\[[^\]]*\]\([^)]*\)", Highlight::Constant, false),
+            pattern(r"\*\*[^*]+\*\*|__[^_]+__", Highlight::Type, false),
+        ],
+        keywords: &[],
+        types: &[],
+        constants: &[],
+        numbers: false,
+    },
+    Syntax {
+        name: "JSON",
+        extensions: &["json"],
+        file_names: &[".prettierrc", ".eslintrc"],
+        line_comments: &[],
+        delimited: &[],
+        patterns: &[
+            pattern(r#"("(?:[^"\\]|\\.)*")\s*:"#, Highlight::Variable, false),
+            pattern(r#""(?:[^"\\]|\\.)*"?"#, Highlight::String, false),
+        ],
+        keywords: &[],
+        types: &[],
+        constants: &["true", "false", "null"],
+        numbers: true,
+    },
+    Syntax {
+        name: "Shell",
+        extensions: &["sh", "bash", "zsh"],
+        file_names: &[".bashrc", ".bash_profile", ".zshrc", ".profile"],
+        line_comments: &["#"],
+        delimited: &[
+            delimited("\"", "\"", Some('\\'), true, Highlight::String),
+            delimited("'", "'", None, true, Highlight::String),
+        ],
+        patterns: &[
+            pattern(
+                r"\$\{[^}]*\}|\$[A-Za-z_]\w*|\$[0-9#?@*$!-]",
+                Highlight::Variable,
+                false,
+            ),
+            pattern(r"([A-Za-z_]\w*)=", Highlight::Variable, false),
+        ],
+        keywords: &[
+            "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case",
+            "esac", "in", "function", "return", "local", "export", "readonly", "set", "unset",
+            "shift", "exit", "break", "continue", "source",
+        ],
+        types: &[],
+        constants: &["true", "false"],
+        numbers: true,
+    },
+];
+
+impl Syntax {
+    pub fn for_path(path: &str) -> Option<&'static Syntax> {
+        let path = Path::new(path);
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        let extension = path.extension().and_then(|ext| ext.to_str());
+
+        SYNTAXES.iter().find(|syntax| {
+            file_name.is_some_and(|name| syntax.file_names.contains(&name))
+                || extension.is_some_and(|ext| syntax.extensions.contains(&ext))
+        })
+    }
+}
+
+/// What is still open at the end of a line.
+#[derive(Clone, Copy, PartialEq)]
+enum LineState {
+    Normal,
+    // index into Syntax::delimited
+    Inside(usize),
+}
+
+/// Tokenizes lines of one document, remembering the state at the start of
+/// every line so a change only re-tokenizes from the changed line onward.
+pub struct Highlighter {
+    syntax: Option<&'static Syntax>,
+    patterns: Vec<(Regex, &'static Pattern)>,
+    // states[i] is the state at the start of line i
+    states: Vec<LineState>,
+}
+
+impl Highlighter {
+    pub fn new(syntax: Option<&'static Syntax>) -> Self {
+        let patterns = syntax
+            .map(|syntax| {
+                syntax
+                    .patterns
+                    .iter()
+                    .filter_map(|pattern| {
+                        let regex = Regex::new(&format!("^(?:{})", pattern.regex)).ok()?;
+                        Some((regex, pattern))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            syntax,
+            patterns,
+            states: vec![LineState::Normal],
+        }
+    }
+
+    pub fn get_name(&self) -> Option<&'static str> {
+        self.syntax.map(|syntax| syntax.name)
+    }
+
+    /// Forgets everything tokenized after line `row` changed.
+    pub fn invalidate(&mut self, row: usize) {
+        self.states.truncate(row + 1);
+    }
+
+    pub fn spans(&mut self, buffer: &Buffer, row: usize, line: &str) -> Vec<Span> {
+        let Some(syntax) = self.syntax else {
+            return vec![];
+        };
+        if self.states.len() <= row {
+            let start = self.states.len() - 1;
+            let mut state = self.states[start];
+            for line in buffer.lines_from(start).take(row - start) {
+                state = self.tokenize(syntax, line, state).1;
+                self.states.push(state);
+            }
+        }
+        let (spans, end_state) = self.tokenize(syntax, line, self.states[row]);
+        if self.states.len() == row + 1 {
+            self.states.push(end_state);
+        }
+        spans
+    }
+
+    fn tokenize(&self, syntax: &Syntax, line: &str, state: LineState) -> (Vec<Span>, LineState) {
+        let mut spans = vec![];
+        let mut idx = 0;
+
+        if let LineState::Inside(i) = state {
+            match Self::find_close(&syntax.delimited[i], line, 0) {
+                Some(end) => {
+                    spans.push(Span::new(0..end, syntax.delimited[i].highlight));
+                    idx = end;
+                }
+                None => {
+                    spans.push(Span::new(0..line.len(), syntax.delimited[i].highlight));
+                    return (spans, state);
+                }
+            }
+        }
+
+        let first_non_blank = line.len() - line.trim_start().len();
+        'outer: while idx < line.len() {
+            let rest = &line[idx..];
+
+            for (regex, pattern) in self.patterns.iter() {
+                if pattern.line_start && idx != first_non_blank {
+                    continue;
+                }
+                if let Some(caps) = regex.captures(rest) {
+                    let whole = caps.get(0).map_or(0..0, |m| m.range());
+                    if whole.is_empty() {
+                        continue;
+                    }
+                    let part = caps.get(1).map_or(whole.clone(), |m| m.range());
+                    spans.push(Span::new(
+                        idx + part.start..idx + part.end,
+                        pattern.highlight,
+                    ));
+                    idx += whole.end;
+                    continue 'outer;
+                }
+            }
+
+            if syntax
+                .line_comments
+                .iter()
+                .any(|comment| rest.starts_with(comment))
+            {
+                spans.push(Span::new(idx..line.len(), Highlight::Comment));
+                break;
+            }
+
+            for (i, delimited) in syntax.delimited.iter().enumerate() {
+                if !rest.starts_with(delimited.open) {
+                    continue;
+                }
+                let from = idx + delimited.open.len();
+                match Self::find_close(delimited, line, from) {
+                    Some(end) => {
+                        spans.push(Span::new(idx..end, delimited.highlight));
+                        idx = end;
+                        continue 'outer;
+                    }
+                    None => {
+                        spans.push(Span::new(idx..line.len(), delimited.highlight));
+                        let state = if delimited.multiline {
+                            LineState::Inside(i)
+                        } else {
+                            LineState::Normal
+                        };
+                        return (spans, state);
+                    }
+                }
+            }
+
+            let ch = rest.chars().next().unwrap_or(' ');
+            if ch.is_alphanumeric() || ch == '_' {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                let highlight = if syntax.numbers && ch.is_ascii_digit() {
+                    Some(Highlight::Number)
+                } else if syntax.keywords.contains(&word) {
+                    Some(Highlight::Keyword)
+                } else if syntax.types.contains(&word) {
+                    Some(Highlight::Type)
+                } else if syntax.constants.contains(&word) {
+                    Some(Highlight::Constant)
+                } else {
+                    None
+                };
+                if let Some(highlight) = highlight {
+                    spans.push(Span::new(idx..idx + len, highlight));
+                }
+                idx += len;
+            } else {
+                idx += ch.len_utf8();
+            }
+        }
+        (spans, LineState::Normal)
+    }
+
+    // byte index right after the closing delimiter, searching from `from`
+    fn find_close(delimited: &Delimited, line: &str, from: usize) -> Option<usize> {
+        let mut chars = line[from..].char_indices();
+        while let Some((i, ch)) = chars.next() {
+            if Some(ch) == delimited.escape {
+                chars.next();
+            } else if line[from + i..].starts_with(delimited.close) {
+                return Some(from + i + delimited.close.len());
+            }
+        }
+        None
+    }
+}
@@ -1,59 +1,238 @@
-use std::{fmt::Display, io::stdout, io::Write};
+use std::{fmt::Display, sync::mpsc::Sender};
 
-use termion::{
-    raw::IntoRawMode,
-    screen::{IntoAlternateScreen, ToAlternateScreen, ToMainScreen},
-};
+use termion::screen::{ToAlternateScreen, ToMainScreen};
+use unicode_segmentation::UnicodeSegmentation;
 
-use crate::common::Size;
+use crate::{backend::Backend, common::Size, event_loop::EditorEvent, text};
+
+// a scroll of fewer rows is cheaper to redraw
+const MIN_SCROLL_ROWS: usize = 3;
+
+/// One screen cell as drawn.
+#[derive(Clone, PartialEq, Eq)]
+struct Cell {
+    // grapheme drawn there, empty for the second cell of a wide one
+    symbol: String,
+    // SGR escapes in effect, empty for the default style
+    style: String,
+}
+
+impl Cell {
+    fn blank() -> Self {
+        Self {
+            symbol: " ".to_string(),
+            style: String::new(),
+        }
+    }
+}
 
 pub struct Terminal {
     pub size: Size,
-    screen: termion::screen::AlternateScreen<termion::raw::RawTerminal<std::io::Stdout>>,
+    backend: Box<dyn Backend>,
+    // what the terminal shows, rows of cells, empty before the first draw
+    front: Vec<Vec<Cell>>,
 }
 
 impl Terminal {
-    pub fn new() -> Self {
-        let screen = stdout()
-            .into_raw_mode()
-            .unwrap()
-            .into_alternate_screen()
-            .unwrap();
-        let size = termion::terminal_size().unwrap();
-
+    pub fn new(backend: Box<dyn Backend>) -> Self {
         Self {
-            screen,
-            size: Size {
-                height: size.1 as usize,
-                width: size.0 as usize,
-            },
+            size: backend.size(),
+            backend,
+            front: vec![],
         }
     }
     pub fn print(&mut self, message: impl Display) {
-        // print!("{}", message);
-        write!(self.screen, "{}", message).unwrap();
+        self.backend.write(&message.to_string());
     }
+    /// Has the terminal send its input and resizes to `events`.
+    pub fn listen(&mut self, events: Sender<EditorEvent>) {
+        self.backend.listen(events);
+    }
+    /// Moves the cursor, which `draw` hides, and shows it again.
     pub fn set_cursor_pos(&mut self, row: usize, col: usize) {
-        self.print(format!("{}", termion::cursor::Goto(col as u16, row as u16)));
+        self.print(format!(
+            "{}{}",
+            termion::cursor::Goto(col as u16, row as u16),
+            termion::cursor::Show
+        ));
     }
     pub fn leave_alternate_screen(&mut self) {
-        write!(self.screen, "{}", ToMainScreen).unwrap();
+        self.print(ToMainScreen);
     }
     pub fn enter_alternate_screen(&mut self) {
-        write!(self.screen, "{}", ToAlternateScreen).unwrap();
-    }
-    pub fn clear(&mut self) {
-        self.print(termion::clear::All);
+        self.print(ToAlternateScreen);
     }
 
     pub fn sync_terminal_size(&mut self) {
-        let size = termion::terminal_size().unwrap();
+        self.size = self.backend.size();
+    }
+
+    /// Puts `lines` on the screen, one per row, writing only the cells that
+    /// differ from the last draw. Lines may hold SGR escapes.
+    pub fn draw(&mut self, lines: &[String]) {
+        let Size { width, height } = self.size;
+        let mut back: Vec<Vec<Cell>> = lines
+            .iter()
+            .take(height)
+            .map(|line| parse_cells(line, width))
+            .collect();
+        back.resize(height, vec![Cell::blank(); width]);
+
+        let mut out = termion::cursor::Hide.to_string();
+        if self.front.len() != height || self.front.iter().any(|row| row.len() != width) {
+            out.push_str(termion::clear::All.as_ref());
+            self.front = vec![vec![Cell::blank(); width]; height];
+        }
+        self.scroll(&back, &mut out);
+
+        // style of the text written next, None when unknown
+        let mut pen: Option<&str> = None;
+        // where the terminal cursor is, 0 based
+        let mut at = None;
+        for (row, (front_row, back_row)) in self.front.iter().zip(&back).enumerate() {
+            for (col, (front_cell, cell)) in front_row.iter().zip(back_row).enumerate() {
+                // the second half of a wide grapheme goes with the first
+                if front_cell == cell || cell.symbol.is_empty() {
+                    continue;
+                }
+                if at != Some((row, col)) {
+                    out.push_str(
+                        &termion::cursor::Goto(col as u16 + 1, row as u16 + 1).to_string(),
+                    );
+                }
+                if pen != Some(cell.style.as_str()) {
+                    out.push_str(termion::style::Reset.as_ref());
+                    out.push_str(&cell.style);
+                    pen = Some(&cell.style);
+                }
+                out.push_str(&cell.symbol);
+                at = Some((row, col + text::grapheme_width(&cell.symbol).max(1)));
+            }
+        }
+        out.push_str(termion::style::Reset.as_ref());
+        self.print(out);
+        self.front = back;
+    }
 
-        self.size.width = size.0 as usize;
-        self.size.height = size.1 as usize;
+    // Scrolls a band of full rows that moved by one row up or down as a
+    // whole with a scroll region, like a doc scrolling by a line, so only
+    // the row coming in has to be drawn.
+    fn scroll(&mut self, back: &[Vec<Cell>], out: &mut String) {
+        let front = &self.front;
+        let height = front.len();
+        // rows r where back[r] is front[r + 1], and where back[r + 1] is front[r]
+        let up = longest_run(height.saturating_sub(1), |r| {
+            back[r] == front[r + 1] && back[r] != front[r]
+        });
+        let down = longest_run(height.saturating_sub(1), |r| {
+            back[r + 1] == front[r] && back[r + 1] != front[r + 1]
+        });
+        let (scroll_up, (start, len)) = match up.1 >= down.1 {
+            true => (true, up),
+            false => (false, down),
+        };
+        if len < MIN_SCROLL_ROWS {
+            return;
+        }
+        // the region holds the rows that move and the one coming in
+        let (top, bottom) = (start, start + len);
+        out.push_str(termion::style::Reset.as_ref());
+        out.push_str(&format!("\x1b[{};{}r", top + 1, bottom + 1));
+        match scroll_up {
+            true => out.push_str(&termion::scroll::Up(1).to_string()),
+            false => out.push_str(&termion::scroll::Down(1).to_string()),
+        }
+        out.push_str("\x1b[r");
+
+        let blank = vec![Cell::blank(); self.size.width];
+        match scroll_up {
+            true => {
+                self.front.remove(top);
+                self.front.insert(bottom, blank);
+            }
+            false => {
+                self.front.remove(bottom);
+                self.front.insert(top, blank);
+            }
+        }
     }
 
     pub(crate) fn flush(&mut self) {
-        self.screen.flush().unwrap();
+        self.backend.flush();
+    }
+}
+
+// start and length of the longest run of indices below `len` matching
+fn longest_run(len: usize, matches: impl Fn(usize) -> bool) -> (usize, usize) {
+    let mut best = (0, 0);
+    let mut start = 0;
+    for i in 0..=len {
+        if i < len && matches(i) {
+            continue;
+        }
+        if i - start > best.1 {
+            best = (start, i - start);
+        }
+        start = i + 1;
+    }
+    best
+}
+
+// cells of a line, cut or padded to `width`
+fn parse_cells(line: &str, width: usize) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = Vec::with_capacity(width);
+    let mut style = String::new();
+    let mut rest = line;
+    while cells.len() < width {
+        if let Some(escape) = rest.strip_prefix("\x1b[") {
+            let Some(end) = escape.find(|ch: char| ('@'..='~').contains(&ch)) else {
+                break;
+            };
+            let sequence = &rest[..end + 3];
+            match sequence {
+                "\x1b[m" | "\x1b[0m" => style.clear(),
+                _ if sequence.ends_with('m') => style.push_str(sequence),
+                // anything else would move the cursor, it is dropped
+                _ => {}
+            }
+            rest = &rest[end + 3..];
+            continue;
+        }
+        let Some(g) = rest.graphemes(true).next() else {
+            break;
+        };
+        rest = &rest[g.len()..];
+        // a tab or other control character would move the terminal's
+        // cursor out of step with the grid
+        let g = match g.chars().any(char::is_control) {
+            true => "\u{fffd}",
+            false => g,
+        };
+        let g_width = text::grapheme_width(g);
+        if g_width == 0 {
+            if let Some(last) = cells.last_mut() {
+                last.symbol.push_str(g);
+            }
+            continue;
+        }
+        if g_width > 1 && cells.len() + g_width > width {
+            cells.push(Cell {
+                symbol: " ".to_string(),
+                style: style.clone(),
+            });
+            continue;
+        }
+        cells.push(Cell {
+            symbol: g.to_string(),
+            style: style.clone(),
+        });
+        for _ in 1..g_width {
+            cells.push(Cell {
+                symbol: String::new(),
+                style: style.clone(),
+            });
+        }
     }
+    cells.resize(width, Cell::blank());
+    cells
 }
@@ -0,0 +1,458 @@
+// End-to-end tests driving the editor through a memory backend. Screens are
+// compared with the snapshots in src/snapshots, run the tests with
+// UPDATE_SNAPSHOTS=1 to write them anew after a deliberate change.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use termion::event::{Event, Key, MouseButton, MouseEvent};
+
+use crate::{
+    backend::MemoryBackend, config::Config, editor::Editor, event_loop::Timer, swap,
+    terminal::Terminal,
+};
+
+const WIDTH: usize = 60;
+const HEIGHT: usize = 10;
+
+struct Session {
+    editor: Editor,
+    backend: MemoryBackend,
+    // holds the files of the test, shown as `<dir>` in snapshots
+    dir: PathBuf,
+}
+
+impl Session {
+    /// An editor on a fresh folder holding `files`, names and contents,
+    /// with `open` opened from it.
+    fn new(name: &str, files: &[(&str, &str)], open: Option<&str>) -> Self {
+        Self::with_config(name, files, open, Config::new())
+    }
+
+    fn with_config(
+        name: &str,
+        files: &[(&str, &str)],
+        open: Option<&str>,
+        mut config: Config,
+    ) -> Self {
+        // recent and swap files of each session are kept apart
+        if config.state_dir.is_none() {
+            config.state_dir = Some(temp_dir(&format!("{}-state", name)));
+        }
+        let dir = temp_dir(name);
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        let backend = MemoryBackend::new(WIDTH, HEIGHT);
+        let terminal = Terminal::new(Box::new(backend.clone()));
+        let open = open.map(|file| dir.join(file).display().to_string());
+        Self {
+            editor: Editor::new(config, open, terminal),
+            backend,
+            dir,
+        }
+    }
+
+    fn send(&mut self, events: impl IntoIterator<Item = Event>) -> &mut Self {
+        self.backend.push_events(events);
+        self.editor.run();
+        self
+    }
+
+    fn fire(&mut self, timer: Timer) -> &mut Self {
+        self.backend.fire(timer);
+        self.editor.run();
+        self
+    }
+
+    fn assert_screen(&self, name: &str) {
+        let screen = self
+            .backend
+            .snapshot()
+            .replace(&self.dir.display().to_string(), "<dir>");
+        assert_snapshot(name, &screen);
+    }
+
+    fn read(&self, file: &str) -> String {
+        fs::read_to_string(self.dir.join(file)).unwrap()
+    }
+}
+
+// a fresh folder for one test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("kaelix-test-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn assert_snapshot(name: &str, screen: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/snapshots")
+        .join(format!("{}.snap", name));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, format!("{}\n", screen)).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("no snapshot {}, run with UPDATE_SNAPSHOTS=1", name));
+    assert_eq!(
+        screen,
+        expected.trim_end_matches('\n'),
+        "screen differs from snapshot {}",
+        name
+    );
+}
+
+fn typed(text: &str) -> Vec<Event> {
+    text.chars().map(|ch| Event::Key(Key::Char(ch))).collect()
+}
+
+fn key(key: Key) -> Vec<Event> {
+    vec![Event::Key(key)]
+}
+
+fn click(col: u16, row: u16) -> Vec<Event> {
+    vec![
+        Event::Mouse(MouseEvent::Press(MouseButton::Left, col, row)),
+        Event::Mouse(MouseEvent::Release(col, row)),
+    ]
+}
+
+#[test]
+fn open_file() {
+    let files = [("notes.txt", "first line\nsecond line\n")];
+    let mut session = Session::new("open_file", &files, Some("notes.txt"));
+    session.send([]).assert_screen("open_file");
+}
+
+#[test]
+fn edit_and_save() {
+    let files = [("notes.txt", "first line\nsecond line\n")];
+    let mut session = Session::new("edit_and_save", &files, Some("notes.txt"));
+    session
+        .send(key(Key::Down))
+        .send((0..11).flat_map(|_| key(Key::Right)))
+        .send(typed(" edited"))
+        .assert_screen("edit_and_save_dirty");
+    session
+        .send(key(Key::Ctrl('s')))
+        .assert_screen("edit_and_save_saved");
+    assert_eq!(
+        session.read("notes.txt"),
+        "first line\nsecond line edited\n"
+    );
+}
+
+#[test]
+fn save_new_doc_asks_for_path() {
+    let mut session = Session::new("save_new_doc", &[], None);
+    session
+        .send(typed("hello"))
+        .send(key(Key::Ctrl('s')))
+        .assert_screen("save_new_doc_prompt");
+    let path = session.dir.join("new.txt").display().to_string();
+    session
+        .send(typed(&path))
+        .send(key(Key::Char('\n')))
+        .assert_screen("save_new_doc_saved");
+    assert_eq!(session.read("new.txt"), "hello\n");
+}
+
+#[test]
+fn tabs() {
+    let files = [("a.txt", "in a\n")];
+    let mut session = Session::new("tabs", &files, Some("a.txt"));
+    session
+        .send(key(Key::Ctrl('n')))
+        .send(typed("in new"))
+        .assert_screen("tabs_new");
+    session.send(key(Key::Ctrl('k'))).assert_screen("tabs_prev");
+    // the tab of the new doc, " a.txt | [No name]* |"
+    session.send(click(12, 1)).assert_screen("tabs_click");
+    // unsaved, so closing asks first
+    session
+        .send(key(Key::Ctrl('w')))
+        .assert_screen("tabs_close_prompt");
+    session.send(typed("d")).assert_screen("tabs_closed");
+}
+
+#[test]
+fn split_panes() {
+    let files = [("a.txt", "one\ntwo\nthree\n")];
+    let mut session = Session::new("split_panes", &files, Some("a.txt"));
+    session
+        .send(key(Key::Alt('s')))
+        .send(key(Key::Down))
+        .send(typed("2"))
+        .assert_screen("split_panes_right");
+    session
+        .send(key(Key::Alt('S')))
+        .send(key(Key::Alt('n')))
+        .assert_screen("split_panes_down");
+    session
+        .send(key(Key::Alt('w')))
+        .assert_screen("split_panes_closed");
+}
+
+#[test]
+fn scrolling_redraws_the_screen() {
+    let lines: Vec<String> = (1..=40).map(|i| format!("line {}", i)).collect();
+    let content = lines.join("\n");
+    let files = [("numbers.txt", content.as_str())];
+    let mut session = Session::new("scrolling", &files, Some("numbers.txt"));
+    session
+        .send((0..12).flat_map(|_| key(Key::Down)))
+        .assert_screen("scrolling_down");
+    session
+        .send((0..10).flat_map(|_| key(Key::Up)))
+        .assert_screen("scrolling_up");
+}
+
+#[test]
+fn resize_redraws_the_screen() {
+    let files = [("a.txt", "some text\nmore text\n")];
+    let mut session = Session::new("resize", &files, Some("a.txt"));
+    session.send([]);
+    session.backend.resize(30, 6);
+    session.send([]).assert_screen("resize_smaller");
+}
+
+#[test]
+fn tab_width_per_config() {
+    let files = [("a.txt", "\tindented\n")];
+    let session = |tab_width| {
+        let mut config = Config::new();
+        config.general.tab_width = tab_width;
+        Session::with_config("tab_width", &files, Some("a.txt"), config)
+    };
+    // both exist at once and each draws with its own width
+    let (mut two, mut eight) = (session(2), session(8));
+    two.send(key(Key::Right)).assert_screen("tab_width_two");
+    eight.send(key(Key::Right)).assert_screen("tab_width_eight");
+}
+
+#[test]
+fn backup_per_config() {
+    let files = [("a.txt", "saved\n")];
+    let session = |name, backup| {
+        let mut config = Config::new();
+        config.general.backup = backup;
+        Session::with_config(name, &files, Some("a.txt"), config)
+    };
+    let (mut with, mut without) = (session("backup_on", true), session("backup_off", false));
+    for session in [&mut with, &mut without] {
+        session.send(typed("x")).send(key(Key::Ctrl('s')));
+        assert_eq!(session.read("a.txt"), "xsaved\n");
+    }
+    assert_eq!(with.read("a.txt~"), "saved\n");
+    assert!(!without.dir.join("a.txt~").exists());
+}
+
+#[test]
+fn tiny_sizes() {
+    for soft_wrap in [false, true] {
+        let files = [("a.txt", "some text\n\tmore text 日本語\n")];
+        let mut config = Config::new();
+        config.general.soft_wrap = soft_wrap;
+        let mut session = Session::with_config("tiny_sizes", &files, Some("a.txt"), config);
+        // splits, the file tree and prompts with their lists on top
+        let states = [
+            key(Key::Alt('s')),
+            key(Key::Alt('S')),
+            key(Key::Ctrl('b')),
+            key(Key::Ctrl('p')),
+            key(Key::Esc),
+            key(Key::Ctrl('o')),
+            key(Key::Esc),
+            key(Key::Ctrl('f')),
+        ];
+        for state in states {
+            session.send(state);
+            for width in [0, 1, 2, 3, 5, 8, 13] {
+                for height in [0, 1, 2, 3, 4, 6] {
+                    session.backend.resize(width, height);
+                    session.send(typed("x"));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn disk_changes_show_without_input() {
+    let files = [("a.txt", "before\n")];
+    let mut session = Session::new("disk_changes", &files, Some("a.txt"));
+    session.send([]);
+    fs::write(session.dir.join("a.txt"), "after\n").unwrap();
+    session
+        .fire(Timer::DiskCheck)
+        .assert_screen("disk_changes_prompt");
+    session
+        .send(typed("r"))
+        .assert_screen("disk_changes_reloaded");
+}
+
+#[test]
+fn file_deleted_on_disk() {
+    let files = [("a.txt", "before\n")];
+    let mut session = Session::new("deleted_on_disk", &files, Some("a.txt"));
+    session.send([]);
+    fs::remove_file(session.dir.join("a.txt")).unwrap();
+    session
+        .fire(Timer::DiskCheck)
+        .assert_screen("deleted_on_disk_prompt");
+    // kept, the buffer counts as unsaved and is no longer asked about
+    session
+        .send(typed("k"))
+        .fire(Timer::DiskCheck)
+        .assert_screen("deleted_on_disk_kept");
+    session.send(key(Key::Ctrl('s')));
+    assert_eq!(session.read("a.txt"), "before\n");
+
+    fs::remove_file(session.dir.join("a.txt")).unwrap();
+    session.fire(Timer::DiskCheck).send(typed("w"));
+    assert_eq!(session.read("a.txt"), "before\n");
+}
+
+// a config keeping its state in a fresh folder, and the swap folder in it
+fn config_with_state(name: &str) -> (Config, PathBuf) {
+    let state_dir = temp_dir(&format!("{}-state", name));
+    let mut config = Config::new();
+    config.state_dir = Some(state_dir.clone());
+    (config, swap::dir(&state_dir))
+}
+
+// writes `file` as a swap file owned by `pid`, for the doc at `path` if any
+fn write_swap_file(file: &Path, pid: u32, path: &str, content: &str) {
+    fs::create_dir_all(file.parent().unwrap()).unwrap();
+    let content = format!("kaelix swap\npid {}\npath {}\n\n{}", pid, path, content);
+    fs::write(file, content).unwrap();
+}
+
+#[test]
+fn recover_swap_file() {
+    let (config, swap_dir) = config_with_state("recover_swap");
+    let path = temp_dir("recover_swap").join("a.txt").display().to_string();
+    // left by an editor that died, no process has that id
+    let swap = swap::file_for(&swap_dir, &path).unwrap();
+    write_swap_file(&swap, 1 << 30, &path, "unsaved\n");
+
+    let files = [("a.txt", "saved\n")];
+    let mut session = Session::with_config("recover_swap", &files, Some("a.txt"), config);
+    session.send([]).assert_screen("recover_swap_prompt");
+    session
+        .send(typed("r"))
+        .assert_screen("recover_swap_recovered");
+    // taken over by this editor
+    session.fire(Timer::Autosave);
+    let pid = format!("pid {}", process::id());
+    assert!(fs::read_to_string(&swap).unwrap().contains(&pid));
+    session.send(key(Key::Ctrl('s')));
+    assert_eq!(session.read("a.txt"), "unsaved\n");
+    assert!(!swap.exists());
+}
+
+#[test]
+fn keep_swap_file() {
+    let (config, swap_dir) = config_with_state("keep_swap");
+    let path = temp_dir("keep_swap").join("a.txt").display().to_string();
+    let kept = swap::file_for(&swap_dir, &path).unwrap();
+    write_swap_file(&kept, 1 << 30, &path, "unsaved\n");
+
+    let files = [("a.txt", "saved\n")];
+    let mut session = Session::with_config("keep_swap", &files, Some("a.txt"), config);
+    session
+        .send(typed("k"))
+        .send(typed("x"))
+        .fire(Timer::Autosave);
+    // the edits go to a swap file of their own
+    let own = swap::file_for(&swap_dir, &path).unwrap();
+    assert_ne!(own, kept);
+    assert!(fs::read_to_string(&own).unwrap().ends_with("\n\nxsaved\n"));
+    session.send(key(Key::Ctrl('s')));
+    assert!(!own.exists());
+    assert!(fs::read_to_string(&kept)
+        .unwrap()
+        .ends_with("\n\nunsaved\n"));
+}
+
+#[test]
+fn file_open_in_another_editor() {
+    let (config, swap_dir) = config_with_state("open_elsewhere");
+    let path = temp_dir("open_elsewhere")
+        .join("a.txt")
+        .display()
+        .to_string();
+    // init is always running
+    let theirs = swap::file_for(&swap_dir, &path).unwrap();
+    write_swap_file(&theirs, 1, &path, "theirs\n");
+
+    let files = [("a.txt", "saved\n")];
+    let mut session = Session::with_config("open_elsewhere", &files, Some("a.txt"), config);
+    session.send([]).assert_screen("open_elsewhere_warning");
+    session
+        .send(typed("x"))
+        .fire(Timer::Autosave)
+        .send(key(Key::Ctrl('s')));
+    assert_eq!(session.read("a.txt"), "xsaved\n");
+    assert!(fs::read_to_string(&theirs)
+        .unwrap()
+        .ends_with("\n\ntheirs\n"));
+}
+
+#[test]
+fn recover_untitled_docs() {
+    for choice in ["k", "d"] {
+        let name = format!("recover_untitled_{}", choice);
+        let (config, swap_dir) = config_with_state(&name);
+        // left by an editor that died, no process has that id
+        let swap = swap_dir.join(format!("untitled-{}-0.swp", 1 << 30));
+        write_swap_file(&swap, 1 << 30, "", "unsaved\n");
+
+        let mut session = Session::with_config(&name, &[], None, config);
+        session.send([]).assert_screen("recover_untitled_prompt");
+        session
+            .send(typed(choice))
+            .assert_screen(&format!("recover_untitled_{}", choice));
+        match choice {
+            "k" => {
+                let pid = format!("pid {}", process::id());
+                assert!(fs::read_to_string(&swap).unwrap().contains(&pid));
+            }
+            _ => assert!(!swap.exists()),
+        }
+    }
+}
+
+#[test]
+fn vim_huge_counts() {
+    let files = [("a.txt", "one two\nthree four\n")];
+    let mut config = Config::new();
+    config.general.vim_mode = true;
+    let mut session = Session::with_config("vim_huge_counts", &files, Some("a.txt"), config);
+    let huge = "9".repeat(30);
+    // each would loop or allocate for ages without the counts cut short
+    for command in ["w", "b", "e", "j", "l", "$", "G", "dd", "u"] {
+        let keys: Vec<Event> = typed(&huge).into_iter().chain(typed(command)).collect();
+        session.send(keys);
+    }
+    session.assert_screen("vim_huge_counts");
+}
+
+#[test]
+fn search_for_a_tab() {
+    let files = [("a.txt", "no tab\nwith\ttab\n")];
+    let mut session = Session::new("search_for_a_tab", &files, Some("a.txt"));
+    session
+        .send(key(Key::Ctrl('f')))
+        .send(key(Key::Char('\t')))
+        .assert_screen("search_for_a_tab_prompt");
+    session
+        .send(key(Key::Char('\n')))
+        .assert_screen("search_for_a_tab");
+}
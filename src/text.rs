@@ -0,0 +1,180 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use std::ops::Range;
+
+use crate::{
+    highlight::{self, Highlight, Span},
+    theme::Theme,
+};
+
+// Columns used by the editor count grapheme clusters, what the user sees as
+// one character. Screen positions count terminal cells, a CJK ideograph or
+// an emoji takes two of them, a tab takes `tab_width` of them.
+
+pub fn grapheme_count(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+/// Byte index where grapheme `col` starts, `s.len()` when `col` is past the end.
+pub fn byte_index(s: &str, col: usize) -> usize {
+    s.grapheme_indices(true)
+        .nth(col)
+        .map_or(s.len(), |(idx, _)| idx)
+}
+
+/// Cells of a grapheme of text already drawn, control characters, tabs
+/// among them, take the one cell of their replacement.
+pub fn grapheme_width(g: &str) -> usize {
+    if g.chars().any(char::is_control) {
+        1
+    } else {
+        g.width()
+    }
+}
+
+fn cells_of(g: &str, tab_width: usize) -> usize {
+    match g {
+        "\t" => tab_width.max(1),
+        _ => grapheme_width(g),
+    }
+}
+
+pub fn width(s: &str, tab_width: usize) -> usize {
+    s.graphemes(true).map(|g| cells_of(g, tab_width)).sum()
+}
+
+/// Number of cells taken by the first `col` graphemes of `s`.
+pub fn cells_before(s: &str, col: usize, tab_width: usize) -> usize {
+    s.graphemes(true)
+        .take(col)
+        .map(|g| cells_of(g, tab_width))
+        .sum()
+}
+
+/// Grapheme column covering screen cell `cell`, clamped to the end of `s`.
+pub fn col_at_cell(s: &str, cell: usize, tab_width: usize) -> usize {
+    let mut used = 0;
+    for (col, g) in s.graphemes(true).enumerate() {
+        used += cells_of(g, tab_width);
+        if used > cell {
+            return col;
+        }
+    }
+    grapheme_count(s)
+}
+
+/// Grapheme columns where the screen rows of `s` start when it is wrapped
+/// at `width` cells. Rows break after whitespace when they have some.
+pub fn wrap_cols(s: &str, width: usize, tab_width: usize) -> Vec<usize> {
+    let width = width.max(1);
+    let mut starts = vec![0];
+    // cells before each column
+    let mut cells = vec![0];
+    // column after the last whitespace of the current row
+    let mut break_at = None;
+    for (col, g) in s.graphemes(true).enumerate() {
+        let g_width = cells_of(g, tab_width);
+        let start = starts[starts.len() - 1];
+        if cells[col] - cells[start] + g_width > width && col > start {
+            let start = match break_at {
+                Some(at) if cells[col] - cells[at] + g_width <= width => at,
+                _ => col,
+            };
+            starts.push(start);
+            break_at = None;
+        }
+        cells.push(cells[col] + g_width);
+        if char_class(g) == 1 {
+            break_at = Some(col + 1);
+        }
+    }
+    starts
+}
+
+/// Grapheme columns of the word, run of spaces or run of punctuation at `col`.
+pub fn word_range(s: &str, col: usize) -> Range<usize> {
+    let classes: Vec<u8> = s.graphemes(true).map(char_class).collect();
+    let Some(&class) = classes.get(col) else {
+        return col..col;
+    };
+    let start = classes[..col]
+        .iter()
+        .rposition(|c| *c != class)
+        .map_or(0, |idx| idx + 1);
+    let end = classes[col..]
+        .iter()
+        .position(|c| *c != class)
+        .map_or(classes.len(), |idx| col + idx);
+    start..end
+}
+
+/// Kind of a grapheme for word motions: word characters, whitespace
+/// or punctuation.
+pub fn char_class(g: &str) -> u8 {
+    match g.chars().next() {
+        Some(ch) if ch.is_alphanumeric() || ch == '_' => 0,
+        Some(ch) if ch.is_whitespace() => 1,
+        _ => 2,
+    }
+}
+
+/// Part of `s` visible from cell `start` on a screen `len` cells wide.
+///
+/// Tabs are expanded and control characters replaced so the result takes
+/// exactly the cells it claims to. Wide graphemes cut by either edge are
+/// replaced with spaces. `spans` are byte ranges of `s` drawn highlighted.
+pub fn sub_cells(
+    s: &str,
+    start: usize,
+    len: usize,
+    spans: &[Span],
+    theme: &Theme,
+    tab_width: usize,
+) -> String {
+    let mut result = String::new();
+    let end = start + len;
+    let mut cell = 0;
+    let mut current: Option<Highlight> = None;
+
+    for (idx, g) in s.grapheme_indices(true) {
+        if cell >= end {
+            break;
+        }
+        let g_width = cells_of(g, tab_width);
+        let g_end = cell + g_width;
+
+        if g_end > start {
+            let g_highlight = highlight::highlight_at(spans, idx);
+            if g_highlight != current {
+                result.push_str(&highlight::reset());
+                if let Some(g_highlight) = g_highlight {
+                    result.push_str(&theme.escape(g_highlight));
+                }
+                current = g_highlight;
+            }
+        }
+
+        if cell >= start && g_end <= end {
+            push_grapheme(&mut result, g, g_width);
+        } else if g_end > start {
+            let visible = g_end.min(end) - cell.max(start);
+            result.push_str(&" ".repeat(visible));
+        }
+        cell = g_end;
+    }
+    if current.is_some() {
+        result.push_str(&highlight::reset());
+    }
+    result
+}
+
+fn push_grapheme(result: &mut String, g: &str, g_width: usize) {
+    if g == "\t" {
+        result.push_str(&" ".repeat(g_width));
+    } else if g.chars().any(char::is_control) {
+        result.push('\u{fffd}');
+    } else {
+        result.push_str(g);
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use termion::{color, style};
+use toml::Value;
+
+use crate::highlight::Highlight;
+
+// how one kind of highlight is drawn
+#[derive(Clone, Copy, Default)]
+struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Color {
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn parse(spec: &str) -> Result<Self, String> {
+        const NAMES: [&str; 16] = [
+            "black",
+            "red",
+            "green",
+            "yellow",
+            "blue",
+            "magenta",
+            "cyan",
+            "white",
+            "light_black",
+            "light_red",
+            "light_green",
+            "light_yellow",
+            "light_blue",
+            "light_magenta",
+            "light_cyan",
+            "light_white",
+        ];
+        if let Some(hex) = spec.strip_prefix('#') {
+            let channel = |i: usize| {
+                hex.get(i..i + 2)
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+            };
+            return match (hex.len(), channel(0), channel(2), channel(4)) {
+                (6, Some(r), Some(g), Some(b)) => Ok(Color::Rgb(r, g, b)),
+                _ => Err(format!("invalid color `{}`, expected #rrggbb", spec)),
+            };
+        }
+        NAMES
+            .iter()
+            .position(|name| *name == spec)
+            .map(|idx| Color::Ansi(idx as u8))
+            .ok_or_else(|| format!("unknown color `{}`", spec))
+    }
+
+    fn fg(&self) -> String {
+        match *self {
+            Color::Ansi(value) => color::Fg(color::AnsiValue(value)).to_string(),
+            Color::Rgb(r, g, b) => color::Fg(color::Rgb(r, g, b)).to_string(),
+        }
+    }
+
+    fn bg(&self) -> String {
+        match *self {
+            Color::Ansi(value) => color::Bg(color::AnsiValue(value)).to_string(),
+            Color::Rgb(r, g, b) => color::Bg(color::Rgb(r, g, b)).to_string(),
+        }
+    }
+}
+
+fn fg(spec: &str) -> Style {
+    Style {
+        fg: Color::parse(spec).ok(),
+        ..Style::default()
+    }
+}
+
+fn on(fg_spec: &str, bg_spec: &str) -> Style {
+    Style {
+        fg: Color::parse(fg_spec).ok(),
+        bg: Color::parse(bg_spec).ok(),
+        bold: false,
+    }
+}
+
+/// Colors used for every kind of `Highlight`.
+pub struct Theme {
+    styles: HashMap<Highlight, Style>,
+}
+
+impl Theme {
+    pub const NAMES: [&'static str; 3] = ["default", "light", "mono"];
+
+    pub fn new() -> Self {
+        Self::named("default").unwrap_or_else(|| Self {
+            styles: HashMap::new(),
+        })
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        let styles: Vec<(Highlight, Style)> = match name {
+            "default" => vec![
+                (Highlight::LineNumber, fg("green")),
+                (Highlight::Comment, fg("light_black")),
+                (Highlight::String, fg("green")),
+                (Highlight::Keyword, fg("magenta")),
+                (Highlight::Type, fg("yellow")),
+                (Highlight::Number, fg("cyan")),
+                (Highlight::Constant, fg("light_cyan")),
+                (Highlight::Variable, fg("light_blue")),
+                (
+                    Highlight::Heading,
+                    Style {
+                        bold: true,
+                        ..fg("blue")
+                    },
+                ),
+                (Highlight::Match, on("black", "yellow")),
+                (Highlight::CurrentMatch, on("black", "light_red")),
+                (Highlight::Selection, on("white", "blue")),
+            ],
+            "light" => vec![
+                (Highlight::LineNumber, fg("light_black")),
+                (Highlight::Comment, fg("light_black")),
+                (Highlight::String, fg("#207020")),
+                (Highlight::Keyword, fg("#8020a0")),
+                (Highlight::Type, fg("#a06000")),
+                (Highlight::Number, fg("#0060a0")),
+                (Highlight::Constant, fg("#0060a0")),
+                (Highlight::Variable, fg("#204080")),
+                (
+                    Highlight::Heading,
+                    Style {
+                        bold: true,
+                        ..fg("blue")
+                    },
+                ),
+                (Highlight::Match, on("black", "light_yellow")),
+                (Highlight::CurrentMatch, on("white", "red")),
+                (Highlight::Selection, on("black", "#b0c8f0")),
+            ],
+            "mono" => vec![
+                (
+                    Highlight::Heading,
+                    Style {
+                        bold: true,
+                        ..Style::default()
+                    },
+                ),
+                (Highlight::Match, on("black", "white")),
+                (Highlight::CurrentMatch, on("white", "black")),
+                (Highlight::Selection, on("white", "light_black")),
+            ],
+            _ => return None,
+        };
+        Some(Self {
+            styles: styles.into_iter().collect(),
+        })
+    }
+
+    /// Overrides the style of one highlight, `value` is either a color or a
+    /// table with `fg`, `bg` and `bold` keys.
+    pub fn set(&mut self, highlight: Highlight, value: &Value) -> Result<(), String> {
+        let style = match value {
+            Value::String(spec) => Style {
+                fg: Some(Color::parse(spec)?),
+                ..Style::default()
+            },
+            Value::Table(table) => {
+                let mut style = Style::default();
+                for (key, value) in table {
+                    match (key.as_str(), value) {
+                        ("fg", Value::String(spec)) => style.fg = Some(Color::parse(spec)?),
+                        ("bg", Value::String(spec)) => style.bg = Some(Color::parse(spec)?),
+                        ("bold", Value::Boolean(bold)) => style.bold = *bold,
+                        ("fg" | "bg" | "bold", _) => {
+                            return Err(format!("wrong type for `{}`", key))
+                        }
+                        _ => return Err(format!("unknown style key `{}`", key)),
+                    }
+                }
+                style
+            }
+            _ => return Err("expected a color or a style table".to_string()),
+        };
+        self.styles.insert(highlight, style);
+        Ok(())
+    }
+
+    pub fn escape(&self, highlight: Highlight) -> String {
+        let Some(style) = self.styles.get(&highlight) else {
+            return String::new();
+        };
+        let mut escape = String::new();
+        if style.bold {
+            escape.push_str(style::Bold.as_ref());
+        }
+        if let Some(fg) = style.fg {
+            escape.push_str(&fg.fg());
+        }
+        if let Some(bg) = style.bg {
+            escape.push_str(&bg.bg());
+        }
+        escape
+    }
+}
@@ -0,0 +1,713 @@
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{buffer::Buffer, common::Position, text};
+
+// class `text::char_class` gives to whitespace, line ends count as whitespace
+const SPACE: u8 = 1;
+// larger counts are cut to this, more than any buffer has lines or words
+const MAX_COUNT: usize = 1_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+            Mode::VisualLine => "V-LINE",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordStart,
+    WordBack,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    // `gg` and `G`, the count picks the line
+    FirstLine,
+    LastLine,
+}
+
+impl Motion {
+    /// Operators work on whole lines with these.
+    pub fn is_linewise(&self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+
+    /// Operators include the character the motion lands on.
+    pub fn is_inclusive(&self) -> bool {
+        matches!(self, Motion::WordEnd)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Object {
+    Word,
+    Quote(char),
+    Bracket(char, char),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Motion(Motion),
+    // `inner` leaves out the surrounding space, quotes or brackets
+    Object { object: Object, inner: bool },
+    // the operator typed twice, as in `dd`
+    Line,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InsertAt {
+    Cursor,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+/// What a complete key sequence asks for, counts are `None` when not typed.
+pub enum Command {
+    // more keys are needed, or the keys made no sense and were dropped
+    Pending,
+    Move(Motion, Option<usize>),
+    Operate {
+        operator: Operator,
+        target: Target,
+        count: Option<usize>,
+        register: Option<char>,
+    },
+    // the operator applied to the visual selection
+    OperateSelection(Operator, Option<char>),
+    SelectObject(Object, bool),
+    Insert(InsertAt),
+    Paste {
+        before: bool,
+        count: Option<usize>,
+        register: Option<char>,
+    },
+    Undo(Option<usize>),
+    Redo(Option<usize>),
+    Visual(Mode),
+    NormalMode,
+    // `:` opens the command line
+    Ex,
+    Search,
+    SearchNext(bool),
+}
+
+enum Parsed {
+    Incomplete,
+    Invalid,
+    Done(Command),
+}
+
+/// Modal key handling, keys typed in normal and visual mode are collected
+/// until they form a command.
+pub struct Vim {
+    pub mode: Mode,
+    keys: Vec<char>,
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Normal,
+            keys: vec![],
+        }
+    }
+
+    /// `None` when the key should get the usual, non modal handling.
+    pub fn process_key(&mut self, key: &Key) -> Option<Command> {
+        if *key == Key::Esc {
+            self.keys.clear();
+            return Some(Command::NormalMode);
+        }
+        if self.mode == Mode::Insert {
+            return None;
+        }
+        let ch = match key {
+            Key::Char(ch) => *ch,
+            Key::Left | Key::Backspace => 'h',
+            Key::Right => 'l',
+            Key::Up => 'k',
+            Key::Down => 'j',
+            Key::Ctrl('r') if self.keys.is_empty() => return Some(Command::Redo(None)),
+            _ => return None,
+        };
+        self.keys.push(match ch {
+            '\n' => 'j',
+            ch => ch,
+        });
+        match self.parse() {
+            Parsed::Incomplete => Some(Command::Pending),
+            Parsed::Invalid => {
+                self.keys.clear();
+                Some(Command::Pending)
+            }
+            Parsed::Done(command) => {
+                self.keys.clear();
+                Some(command)
+            }
+        }
+    }
+
+    // ["x] [count] (operator [count] (operator | motion | object) | motion | command)
+    fn parse(&self) -> Parsed {
+        let keys = &self.keys;
+        let mut idx = 0;
+        let mut register = None;
+        if keys.first() == Some(&'"') {
+            match keys.get(1) {
+                Some(ch) => register = Some(*ch),
+                None => return Parsed::Incomplete,
+            }
+            idx = 2;
+        }
+        let (count, next) = parse_count(keys, idx);
+        let Some(&key) = keys.get(next) else {
+            return Parsed::Incomplete;
+        };
+        idx = next + 1;
+        let visual = matches!(self.mode, Mode::Visual | Mode::VisualLine);
+
+        if let Some(operator) = parse_operator(key) {
+            if visual {
+                return Parsed::Done(Command::OperateSelection(operator, register));
+            }
+            let (motion_count, next) = parse_count(keys, idx);
+            let count = match (count, motion_count) {
+                (None, None) => None,
+                (count, motion_count) => Some(
+                    count
+                        .unwrap_or(1)
+                        .saturating_mul(motion_count.unwrap_or(1))
+                        .min(MAX_COUNT),
+                ),
+            };
+            let target = match keys.get(next) {
+                None => return Parsed::Incomplete,
+                Some(&ch) if ch == key => Target::Line,
+                Some('i' | 'a') => match keys.get(next + 1).map(|ch| parse_object(*ch)) {
+                    None => return Parsed::Incomplete,
+                    Some(None) => return Parsed::Invalid,
+                    Some(Some(object)) => Target::Object {
+                        object,
+                        inner: keys[next] == 'i',
+                    },
+                },
+                Some(_) => match parse_motion(&keys[next..]) {
+                    Parsed::Done(Command::Move(motion, _)) => Target::Motion(motion),
+                    Parsed::Incomplete => return Parsed::Incomplete,
+                    _ => return Parsed::Invalid,
+                },
+            };
+            return Parsed::Done(Command::Operate {
+                operator,
+                target,
+                count,
+                register,
+            });
+        }
+
+        let command = match key {
+            'x' if visual => Command::OperateSelection(Operator::Delete, register),
+            'x' => Command::Operate {
+                operator: Operator::Delete,
+                target: Target::Motion(Motion::Right),
+                count,
+                register,
+            },
+            'p' | 'P' => Command::Paste {
+                before: key == 'P',
+                count,
+                register,
+            },
+            'v' => Command::Visual(Mode::Visual),
+            'V' => Command::Visual(Mode::VisualLine),
+            'i' | 'a' if visual => match keys.get(idx).map(|ch| parse_object(*ch)) {
+                None => return Parsed::Incomplete,
+                Some(None) => return Parsed::Invalid,
+                Some(Some(object)) => Command::SelectObject(object, key == 'i'),
+            },
+            'I' | 'A' | 'o' | 'O' if visual => return Parsed::Invalid,
+            'i' => Command::Insert(InsertAt::Cursor),
+            'a' => Command::Insert(InsertAt::After),
+            'I' => Command::Insert(InsertAt::LineStart),
+            'A' => Command::Insert(InsertAt::LineEnd),
+            'o' => Command::Insert(InsertAt::LineBelow),
+            'O' => Command::Insert(InsertAt::LineAbove),
+            'u' => Command::Undo(count),
+            ':' => Command::Ex,
+            '/' => Command::Search,
+            'n' => Command::SearchNext(true),
+            'N' => Command::SearchNext(false),
+            _ => {
+                return match parse_motion(&keys[next..]) {
+                    Parsed::Done(Command::Move(motion, _)) => {
+                        Parsed::Done(Command::Move(motion, count))
+                    }
+                    parsed => parsed,
+                }
+            }
+        };
+        Parsed::Done(command)
+    }
+}
+
+fn parse_count(keys: &[char], start: usize) -> (Option<usize>, usize) {
+    let mut idx = start;
+    let mut count: Option<usize> = None;
+    while let Some(digit) = keys.get(idx).and_then(|ch| ch.to_digit(10)) {
+        // a leading 0 is the motion to the line start
+        if digit == 0 && count.is_none() {
+            break;
+        }
+        let value = count
+            .unwrap_or(0)
+            .saturating_mul(10)
+            .saturating_add(digit as usize);
+        count = Some(value.min(MAX_COUNT));
+        idx += 1;
+    }
+    (count, idx)
+}
+
+fn parse_operator(key: char) -> Option<Operator> {
+    match key {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        _ => None,
+    }
+}
+
+fn parse_motion(keys: &[char]) -> Parsed {
+    let motion = match keys {
+        ['h', ..] => Motion::Left,
+        ['l', ..] | [' ', ..] => Motion::Right,
+        ['k', ..] => Motion::Up,
+        ['j', ..] => Motion::Down,
+        ['w', ..] => Motion::WordStart,
+        ['b', ..] => Motion::WordBack,
+        ['e', ..] => Motion::WordEnd,
+        ['0', ..] => Motion::LineStart,
+        ['$', ..] => Motion::LineEnd,
+        ['G', ..] => Motion::LastLine,
+        ['g'] => return Parsed::Incomplete,
+        ['g', 'g', ..] => Motion::FirstLine,
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Done(Command::Move(motion, None))
+}
+
+fn parse_object(key: char) -> Option<Object> {
+    let object = match key {
+        'w' => Object::Word,
+        '"' | '\'' | '`' => Object::Quote(key),
+        '(' | ')' | 'b' => Object::Bracket('(', ')'),
+        '{' | '}' | 'B' => Object::Bracket('{', '}'),
+        '[' | ']' => Object::Bracket('[', ']'),
+        '<' | '>' => Object::Bracket('<', '>'),
+        _ => return None,
+    };
+    Some(object)
+}
+
+/// Steps through the buffer one grapheme at a time. The end of every line
+/// is a position of its own, standing for the line break.
+struct Walker<'a> {
+    buffer: &'a Buffer,
+    pos: Position,
+    graphemes: Vec<&'a str>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(buffer: &'a Buffer, pos: Position) -> Self {
+        Self {
+            buffer,
+            pos,
+            graphemes: buffer.line(pos.row).graphemes(true).collect(),
+        }
+    }
+
+    fn grapheme(&self) -> Option<&'a str> {
+        self.graphemes.get(self.pos.col).copied()
+    }
+
+    fn class(&self) -> u8 {
+        self.grapheme().map_or(SPACE, text::char_class)
+    }
+
+    fn at_empty_line(&self) -> bool {
+        self.graphemes.is_empty()
+    }
+
+    fn set_row(&mut self, row: usize) {
+        self.pos.row = row;
+        self.graphemes = self.buffer.line(row).graphemes(true).collect();
+    }
+
+    fn forward(&mut self) -> bool {
+        if self.pos.col < self.graphemes.len() {
+            self.pos.col += 1;
+        } else if self.pos.row + 1 < self.buffer.len_lines() {
+            self.set_row(self.pos.row + 1);
+            self.pos.col = 0;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn backward(&mut self) -> bool {
+        if self.pos.col > 0 {
+            self.pos.col -= 1;
+        } else if self.pos.row > 0 {
+            self.set_row(self.pos.row - 1);
+            self.pos.col = self.graphemes.len();
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+pub fn word_start(buffer: &Buffer, pos: Position) -> Position {
+    let mut walker = Walker::new(buffer, pos);
+    let class = walker.class();
+    if class != SPACE {
+        while walker.class() == class {
+            if !walker.forward() {
+                return walker.pos;
+            }
+        }
+    }
+    // empty lines count as words
+    while walker.class() == SPACE && !(walker.at_empty_line() && walker.pos != pos) {
+        if !walker.forward() {
+            break;
+        }
+    }
+    walker.pos
+}
+
+pub fn word_end(buffer: &Buffer, pos: Position) -> Position {
+    let mut walker = Walker::new(buffer, pos);
+    if !walker.forward() {
+        return pos;
+    }
+    while walker.class() == SPACE {
+        if !walker.forward() {
+            return walker.pos;
+        }
+    }
+    let class = walker.class();
+    while walker.forward() {
+        if walker.class() != class {
+            walker.backward();
+            break;
+        }
+    }
+    walker.pos
+}
+
+pub fn word_back(buffer: &Buffer, pos: Position) -> Position {
+    let mut walker = Walker::new(buffer, pos);
+    if !walker.backward() {
+        return pos;
+    }
+    while walker.class() == SPACE && !walker.at_empty_line() {
+        if !walker.backward() {
+            return walker.pos;
+        }
+    }
+    let class = walker.class();
+    while walker.pos.col > 0 && text::char_class(walker.graphemes[walker.pos.col - 1]) == class {
+        walker.pos.col -= 1;
+    }
+    walker.pos
+}
+
+/// Start and end, exclusive, of a text object around `pos`.
+pub fn object_range(
+    buffer: &Buffer,
+    pos: Position,
+    object: Object,
+    inner: bool,
+) -> Option<(Position, Position)> {
+    match object {
+        Object::Word => {
+            let line = buffer.line(pos.row);
+            let cols = text::word_range(line, pos.col);
+            if cols.is_empty() {
+                return None;
+            }
+            let (mut start, mut end) = (cols.start, cols.end);
+            if !inner {
+                // the space after the word, or before it at the end of the line
+                let len = text::grapheme_count(line);
+                let after = text::word_range(line, end);
+                let before = text::word_range(line, start.saturating_sub(1));
+                let is_space =
+                    |col: usize| line.graphemes(true).nth(col).map(text::char_class) == Some(SPACE);
+                if end < len && is_space(end) {
+                    end = after.end;
+                } else if start > 0 && is_space(start - 1) {
+                    start = before.start;
+                }
+            }
+            Some((
+                Position {
+                    row: pos.row,
+                    col: start,
+                },
+                Position {
+                    row: pos.row,
+                    col: end,
+                },
+            ))
+        }
+        Object::Quote(quote) => {
+            let line = buffer.line(pos.row);
+            let quote = quote.to_string();
+            let quotes: Vec<usize> = line
+                .graphemes(true)
+                .enumerate()
+                .filter(|(_, g)| **g == quote)
+                .map(|(col, _)| col)
+                .collect();
+            // quotes pair up from the line start, the pair around the
+            // cursor or else the next one is used
+            let (open, close) = quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|(_, close)| *close >= pos.col)?;
+            let (start, end) = match inner {
+                true => (open + 1, close),
+                false => (open, close + 1),
+            };
+            Some((
+                Position {
+                    row: pos.row,
+                    col: start,
+                },
+                Position {
+                    row: pos.row,
+                    col: end,
+                },
+            ))
+        }
+        Object::Bracket(open, close) => {
+            let (open, close) = (open.to_string(), close.to_string());
+            let mut walker = Walker::new(buffer, pos);
+            let mut depth = 0;
+            // on a closing bracket the pair is the one it closes
+            if walker.grapheme() == Some(close.as_str()) {
+                depth = 1;
+                if !walker.backward() {
+                    return None;
+                }
+            }
+            loop {
+                match walker.grapheme() {
+                    Some(g) if g == open && depth == 0 => break,
+                    Some(g) if g == open => depth -= 1,
+                    Some(g) if g == close => depth += 1,
+                    _ => {}
+                }
+                if !walker.backward() {
+                    return None;
+                }
+            }
+            let start = walker.pos;
+            depth = 0;
+            loop {
+                if !walker.forward() {
+                    return None;
+                }
+                match walker.grapheme() {
+                    Some(g) if g == close && depth == 0 => break,
+                    Some(g) if g == close => depth -= 1,
+                    Some(g) if g == open => depth += 1,
+                    _ => {}
+                }
+            }
+            let end = walker.pos;
+            if !inner {
+                return Some((
+                    start,
+                    Position {
+                        col: end.col + 1,
+                        ..end
+                    },
+                ));
+            }
+            let mut inner_start = Walker::new(buffer, start);
+            inner_start.forward();
+            Some((inner_start.pos, end))
+        }
+    }
+}
+
+/// Last column of the word at `pos`, what `cw` changes up to. `None` on
+/// whitespace, where `cw` works like `dw`.
+pub fn change_word_end(buffer: &Buffer, pos: Position) -> Option<Position> {
+    let mut walker = Walker::new(buffer, pos);
+    let class = walker.class();
+    if class == SPACE {
+        return None;
+    }
+    while walker.pos.col + 1 < walker.graphemes.len()
+        && text::char_class(walker.graphemes[walker.pos.col + 1]) == class
+    {
+        walker.pos.col += 1;
+    }
+    Some(walker.pos)
+}
+
+pub fn first_non_blank(line: &str) -> usize {
+    line.graphemes(true)
+        .position(|g| text::char_class(g) != SPACE)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(keys: &str) -> Parsed {
+        let mut vim = Vim::new();
+        vim.keys = keys.chars().collect();
+        vim.parse()
+    }
+
+    fn chars(keys: &str) -> Vec<char> {
+        keys.chars().collect()
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(parse_count(&chars("12w"), 0), (Some(12), 2));
+        assert_eq!(parse_count(&chars("w"), 0), (None, 0));
+        // the line start motion, not a count
+        assert_eq!(parse_count(&chars("0"), 0), (None, 0));
+        assert_eq!(parse_count(&chars("d10j"), 1), (Some(10), 3));
+        let huge = "9".repeat(40);
+        assert_eq!(parse_count(&chars(&huge), 0), (Some(MAX_COUNT), 40));
+    }
+
+    #[test]
+    fn motions() {
+        assert!(matches!(
+            parse("3w"),
+            Parsed::Done(Command::Move(Motion::WordStart, Some(3)))
+        ));
+        assert!(matches!(
+            parse("0"),
+            Parsed::Done(Command::Move(Motion::LineStart, None))
+        ));
+        assert!(matches!(parse("g"), Parsed::Incomplete));
+        assert!(matches!(
+            parse("gg"),
+            Parsed::Done(Command::Move(Motion::FirstLine, None))
+        ));
+        assert!(matches!(parse("z"), Parsed::Invalid));
+        let huge = format!("{}w", "9".repeat(40));
+        assert!(matches!(
+            parse(&huge),
+            Parsed::Done(Command::Move(Motion::WordStart, Some(MAX_COUNT)))
+        ));
+    }
+
+    #[test]
+    fn operators() {
+        assert!(matches!(
+            parse("2d3w"),
+            Parsed::Done(Command::Operate {
+                operator: Operator::Delete,
+                target: Target::Motion(Motion::WordStart),
+                count: Some(6),
+                register: None,
+            })
+        ));
+        assert!(matches!(
+            parse("\"ayy"),
+            Parsed::Done(Command::Operate {
+                operator: Operator::Yank,
+                target: Target::Line,
+                count: None,
+                register: Some('a'),
+            })
+        ));
+        assert!(matches!(
+            parse("ci("),
+            Parsed::Done(Command::Operate {
+                operator: Operator::Change,
+                target: Target::Object {
+                    object: Object::Bracket('(', ')'),
+                    inner: true,
+                },
+                ..
+            })
+        ));
+        assert!(matches!(parse("d"), Parsed::Incomplete));
+        assert!(matches!(parse("d2"), Parsed::Incomplete));
+        assert!(matches!(parse("diz"), Parsed::Invalid));
+        let huge = format!("{0}d{0}w", "9".repeat(20));
+        assert!(matches!(
+            parse(&huge),
+            Parsed::Done(Command::Operate {
+                count: Some(MAX_COUNT),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn commands() {
+        assert!(matches!(parse("5u"), Parsed::Done(Command::Undo(Some(5)))));
+        assert!(matches!(
+            parse("\"b3P"),
+            Parsed::Done(Command::Paste {
+                before: true,
+                count: Some(3),
+                register: Some('b'),
+            })
+        ));
+        assert!(matches!(parse("\""), Parsed::Incomplete));
+        assert!(matches!(
+            parse("x"),
+            Parsed::Done(Command::Operate {
+                operator: Operator::Delete,
+                target: Target::Motion(Motion::Right),
+                ..
+            })
+        ));
+    }
+}